@@ -0,0 +1,17 @@
+use std::process::Command;
+
+fn main() {
+    // identify the build by the abbreviated hash of the checked out commit
+    let build_id = Command::new("git")
+        .args(&["rev-parse", "--short=8", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|hash| u32::from_str_radix(hash.trim(), 16).ok())
+        .unwrap_or(0);
+
+    println!("cargo:rustc-env=BUILD_ID={}", build_id);
+    println!("cargo:rerun-if-changed=../../.git/HEAD");
+    println!("cargo:rerun-if-changed=../../.git/refs");
+}
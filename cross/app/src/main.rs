@@ -3,77 +3,155 @@
 
 use core::slice;
 
-use board::{Board, Instant, Scd30, Serial};
+use board::{Board, BusError, Instant, Scd30, Serial};
 use defmt::unwrap;
 use defmt_rtt as _;
 use heapless::{consts, Vec};
-use messages::{Host2Target, Measurement, Target2Host};
+use messages::{
+    Capabilities, ConfigKey, ConfigValue, DeviceInfo, ErrorCode, FirmwareVersion, FullMeasurement,
+    Host2Target, Request, RequestId, Response, Target2Host, PROTOCOL_VERSION,
+};
 use panic_probe as _;
 use rtic::cyccnt::U32Ext;
+use scd30::{TemperatureOffset, Transport};
+
+/// Ambient pressure, in millibar, used for the SCD30's pressure compensation
+const AMBIENT_PRESSURE: u16 = 1_020;
+
+/// Interval, in seconds, between SCD30 measurements
+// NOTE the `host-target-tests` expect a new measurement every 2 seconds
+const MEASUREMENT_INTERVAL: u16 = 2;
 
 #[rtic::app(device = board::pac, monotonic = rtic::cyccnt::CYCCNT)]
 const APP: () = {
     struct Resources {
+        /// Ambient pressure last applied to the SCD30, which can't report it
+        #[init(AMBIENT_PRESSURE)]
+        ambient_pressure: u16,
         #[init(0)]
         count: u32,
+        device_info: DeviceInfo,
         scd30: Scd30,
         serial: Serial,
         #[init(None)]
-        measurement: Option<Measurement>,
+        measurement: Option<FullMeasurement>,
     }
 
     #[init(spawn = [periodic])]
     fn init(cx: init::Context) -> init::LateResources {
         let mut board = Board::init(cx.core.DCB, cx.core.DWT);
 
+        let device_info = DeviceInfo {
+            protocol_version: PROTOCOL_VERSION,
+            firmware_version: FirmwareVersion {
+                major: parse_version(env!("CARGO_PKG_VERSION_MAJOR")),
+                minor: parse_version(env!("CARGO_PKG_VERSION_MINOR")),
+                patch: parse_version(env!("CARGO_PKG_VERSION_PATCH")),
+            },
+            build_id: parse_build_id(env!("BUILD_ID")),
+            scd30_firmware_version: board.scd30.get_firmware_version().ok(),
+            capabilities: Capabilities::LAST_MEASUREMENT
+                | Capabilities::FULL_MEASUREMENT
+                | Capabilities::REMOTE_CONFIG,
+        };
+
         board
             .scd30
-            .start_continuous_measurement()
+            .set_measurement_interval(MEASUREMENT_INTERVAL)
+            .unwrap();
+        board
+            .scd30
+            .start_continuous_measurement(AMBIENT_PRESSURE)
             .unwrap();
         unwrap!(cx.spawn.periodic());
 
         defmt::info!("DONE");
         init::LateResources {
+            device_info,
             scd30: board.scd30,
             serial: board.serial,
         }
     }
 
-    #[idle(resources = [serial, measurement])]
+    #[idle(resources = [ambient_pressure, device_info, scd30, serial, measurement])]
     fn idle(mut cx: idle::Context) -> ! {
+        let ambient_pressure = cx.resources.ambient_pressure;
         let serial = cx.resources.serial;
         let mut rx_buffer = Vec::<u8, consts::U64>::new();
         let mut tx_buffer = [0; 64];
 
         let mut byte = 0;
+        // set when a frame doesn't fit in `rx_buffer`, or when a byte of it was lost to a
+        // receive error (e.g. framing or overrun); the rest of the frame is dropped
+        let mut malformed = false;
         loop {
-            serial.read(slice::from_mut(&mut byte)).unwrap();
+            if serial.read(slice::from_mut(&mut byte)).is_err() {
+                defmt::error!("serial receive error");
+                malformed = true;
+                continue;
+            }
 
             if byte == 0 {
                 defmt::info!("RX bytes={}", &*rx_buffer);
 
-                if let Ok(request) = postcard::from_bytes_cobs::<Host2Target>(&mut rx_buffer) {
-                    match request {
-                        Host2Target::GetLastMeasurement => {
-                            let resp = cx
+                let request = if malformed {
+                    Err(Response {
+                        id: RequestId::UNKNOWN,
+                        message: Target2Host::Error(ErrorCode::MalformedFrame),
+                    })
+                } else {
+                    Request::decode(&mut rx_buffer)
+                };
+
+                let resp = match request {
+                    Ok(request) => {
+                        let measurement = cx.resources.measurement.lock(|opt| opt.clone());
+                        let message = match request.message {
+                            Host2Target::GetLastMeasurement => measurement
+                                .map(|full| Target2Host::Measurement(full.into()))
+                                .unwrap_or(Target2Host::NotReady),
+
+                            Host2Target::GetDeviceInfo => {
+                                Target2Host::DeviceInfo(*cx.resources.device_info)
+                            }
+
+                            Host2Target::GetLastFullMeasurement => measurement
+                                .map(Target2Host::FullMeasurement)
+                                .unwrap_or(Target2Host::NotReady),
+
+                            Host2Target::GetConfig(key) => cx
                                 .resources
-                                .measurement
-                                .lock(|opt| opt.clone())
-                                .map(Target2Host::Measurement)
-                                .unwrap_or(Target2Host::NotReady);
-
-                            let bytes = postcard::to_slice_cobs(&resp, &mut tx_buffer).unwrap();
-                            defmt::info!("TX bytes={}", bytes);
-                            serial.write(bytes).unwrap();
+                                .scd30
+                                .lock(|scd30| get_config(scd30, *ambient_pressure, key))
+                                .map_or_else(sensor_error, Target2Host::Config),
+
+                            Host2Target::SetConfig(value) => cx
+                                .resources
+                                .scd30
+                                .lock(|scd30| set_config(scd30, ambient_pressure, value))
+                                .map_or_else(sensor_error, Target2Host::Config),
+                        };
+
+                        Response {
+                            id: request.id,
+                            message,
                         }
                     }
-                } else {
-                    defmt::error!("postcard deserialization error")
-                }
+
+                    Err(resp) => {
+                        defmt::error!("couldn't decode the request");
+                        resp
+                    }
+                };
+
+                let bytes = postcard::to_slice_cobs(&resp, &mut tx_buffer).unwrap();
+                defmt::info!("TX bytes={}", bytes);
+                serial.write(bytes).unwrap();
 
                 rx_buffer.clear();
-            } else {
-                rx_buffer.push(byte).unwrap();
+                malformed = false;
+            } else if rx_buffer.push(byte).is_err() {
+                malformed = true;
             }
         }
     }
@@ -94,10 +172,12 @@ const APP: () = {
                 if let Ok(sensor_data) = scd30.read_measurement() {
                     defmt::info!("{}", sensor_data);
 
-                    *cx.resources.measurement = Some(Measurement {
+                    *cx.resources.measurement = Some(FullMeasurement {
                         id: *cx.resources.count,
                         timestamp,
                         co2: sensor_data.co2,
+                        temperature: sensor_data.temperature,
+                        humidity: sensor_data.humidity,
                     });
                     *cx.resources.count += 1;
                 } else {
@@ -113,3 +193,81 @@ const APP: () = {
         fn RTC0();
     }
 };
+
+/// Reports a failed sensor operation to the host
+fn sensor_error<E>(error: scd30::Error<E>) -> Target2Host
+where
+    E: BusError,
+{
+    defmt::error!("sensor error");
+
+    Target2Host::Error(match error {
+        scd30::Error::I2c(e) | scd30::Error::Serial(e) if e.is_busy() => ErrorCode::Busy,
+        scd30::Error::I2c(_) | scd30::Error::Serial(_) => ErrorCode::SensorBus,
+        scd30::Error::InvalidCrc => ErrorCode::SensorCrc,
+        scd30::Error::InvalidArgument => ErrorCode::InvalidArgument,
+        scd30::Error::InvalidResponse
+        | scd30::Error::NonFiniteMeasurement
+        | scd30::Error::MeasurementOutOfRange
+        | scd30::Error::UnexpectedStatus(_)
+        | scd30::Error::Timeout => ErrorCode::SensorResponse,
+    })
+}
+
+/// Returns the value of the setting `key` that is in effect
+fn get_config<T>(
+    scd30: &mut scd30::Scd30<T>,
+    ambient_pressure: u16,
+    key: ConfigKey,
+) -> Result<ConfigValue, scd30::Error<T::Error>>
+where
+    T: Transport,
+{
+    Ok(match key {
+        ConfigKey::MeasurementInterval => {
+            ConfigValue::MeasurementInterval(scd30.get_measurement_interval()?)
+        }
+        ConfigKey::AmbientPressure => ConfigValue::AmbientPressure(ambient_pressure),
+        ConfigKey::TemperatureOffset => {
+            ConfigValue::TemperatureOffset(scd30.get_temperature_offset()?.as_centikelvin())
+        }
+        ConfigKey::AutomaticSelfCalibration => {
+            ConfigValue::AutomaticSelfCalibration(scd30.get_automatic_self_calibration()?)
+        }
+    })
+}
+
+/// Applies `value` and returns the value of the setting that is in effect afterwards
+fn set_config<T>(
+    scd30: &mut scd30::Scd30<T>,
+    ambient_pressure: &mut u16,
+    value: ConfigValue,
+) -> Result<ConfigValue, scd30::Error<T::Error>>
+where
+    T: Transport,
+{
+    match value {
+        ConfigValue::MeasurementInterval(interval) => scd30.set_measurement_interval(interval),
+        ConfigValue::AmbientPressure(pressure) => scd30
+            .set_ambient_pressure(pressure)
+            .map(|()| *ambient_pressure = pressure),
+        ConfigValue::TemperatureOffset(offset) => {
+            scd30.set_temperature_offset(TemperatureOffset::from_centikelvin(offset))
+        }
+        ConfigValue::AutomaticSelfCalibration(enabled) => {
+            scd30.set_automatic_self_calibration(enabled)
+        }
+    }?;
+
+    get_config(scd30, *ambient_pressure, value.key())
+}
+
+/// Parses a component of the crate version
+fn parse_version(component: &str) -> u8 {
+    component.parse().unwrap_or(u8::MAX)
+}
+
+/// Parses the build ID set by the build script
+fn parse_build_id(build_id: &str) -> u32 {
+    build_id.parse().unwrap_or(0)
+}
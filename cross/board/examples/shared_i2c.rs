@@ -0,0 +1,52 @@
+//! Two device drivers sharing the I2C bus `TWIM0`
+//!
+//! Each driver gets its own copy of the `board::I2c` handle: the first one owns it, the second
+//! one borrows it with `scd30::ByRef`. No other device is attached to the board yet, so both
+//! drivers talk to the SCD30; a driver for another I2C device shares the bus the same way.
+//! Requires the SCD30's I2C interface, i.e. the board built without the `modbus` feature
+//!
+//! ``` console
+//! $ cargo run -p board --example shared_i2c
+//! ```
+
+#![no_main]
+#![no_std]
+
+use core::time::Duration;
+
+use board::{Board, Delay, Instant};
+use defmt::unwrap;
+use defmt_rtt as _;
+use panic_probe as _;
+use scd30::ByRef;
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let cm_periph = unwrap!(cortex_m::Peripherals::take());
+    let board = Board::init(cm_periph.DCB, cm_periph.DWT);
+
+    let mut first = scd30::Scd30::init(board.i2c, Delay);
+    let mut i2c = board.i2c;
+    let mut second = scd30::Scd30::init(ByRef(&mut i2c), Delay);
+
+    // the drivers take turns on the bus
+    let version = first.get_firmware_version().unwrap();
+    defmt::info!("firmware version: {=[u8]}", version[..]);
+    let interval = second.get_measurement_interval().unwrap();
+    defmt::info!("measurement interval: {=u16} s", interval);
+
+    first.start_continuous_measurement(0).unwrap();
+    let data = second
+        .wait_for_measurement::<Instant, _>(
+            &mut Delay,
+            Duration::from_millis(100),
+            Duration::from_secs(u64::from(interval) + 1),
+        )
+        .unwrap();
+    defmt::info!("{}", data);
+    first.stop_continuous_measurement().unwrap();
+
+    loop {
+        cortex_m::asm::bkpt()
+    }
+}
@@ -1,10 +1,23 @@
 #![no_std]
 
+use core::cell::UnsafeCell;
+#[cfg(feature = "modbus")]
+use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
 
 use cortex_m::peripheral::{DCB, DWT};
 use defmt::unwrap;
+use embedded_hal::blocking::{delay::DelayUs, i2c};
+#[cfg(feature = "modbus")]
+use embedded_hal::{blocking, serial};
 pub use nrf52840_hal::pac;
+#[cfg(feature = "modbus")]
+use nrf52840_hal::{
+    gpio::p1,
+    pac::{TIMER1, UARTE1},
+    Timer,
+};
 use nrf52840_hal::{
     gpio::{p0, Level},
     pac::{TWIM0, UARTE0},
@@ -16,12 +29,17 @@ pub use scd30::SensorData;
 
 const CYCCNT_FREQUENCY_MHZ: u32 = 64;
 
-pub type Scd30 = scd30::Scd30<Twim<TWIM0>>;
+#[cfg(not(feature = "modbus"))]
+pub type Scd30 = scd30::Scd30<scd30::I2cTransport<I2c, Delay>>;
+#[cfg(feature = "modbus")]
+pub type Scd30 = scd30::Scd30<scd30::ModbusTransport<ModbusSerial, Delay, Instant>>;
 pub type Serial = Uarte<UARTE0>;
 
 /// Peripherals and on-board sensors
 pub struct Board {
     pub scd30: Scd30,
+    /// Handle to the I2C bus for devices other than the SCD30; see `examples/shared_i2c.rs`
+    pub i2c: I2c,
     pub serial: Serial,
 }
 
@@ -43,6 +61,47 @@ impl Board {
         let sda = p0.p0_31.into_floating_input().degrade();
         let pins = twim::Pins { scl, sda };
         let twim = Twim::new(dev_periph.TWIM0, pins, twim::Frequency::K100);
+        let bus = unwrap!(cortex_m::singleton!(
+            : SharedTwim = SharedTwim {
+                busy: AtomicBool::new(false),
+                twim: UnsafeCell::new(twim),
+            }
+        ));
+        let i2c = I2c(bus);
+
+        #[cfg(not(feature = "modbus"))]
+        let scd30 = Scd30::init(i2c, Delay);
+
+        // the SCD30's SEL pin must be pulled high to select the Modbus interface
+        #[cfg(feature = "modbus")]
+        let scd30 = {
+            let p1 = p1::Parts::new(dev_periph.P1);
+
+            // TXD = P1.01
+            // RXD = P1.02
+            let txd = p1.p1_01.into_push_pull_output(Level::High).degrade();
+            let rxd = p1.p1_02.into_floating_input().degrade();
+            let pins = uarte::Pins {
+                txd,
+                rxd,
+                cts: None,
+                rts: None,
+            };
+
+            let uarte = Uarte::new(
+                dev_periph.UARTE1,
+                pins,
+                Parity::EXCLUDED,
+                Baudrate::BAUD19200,
+            );
+
+            let timer = Timer::new(dev_periph.TIMER1);
+
+            Scd30::with_transport(scd30::ModbusTransport::new(
+                ModbusSerial { uarte, timer },
+                Delay,
+            ))
+        };
 
         // TXD = 06
         // RXD = 08
@@ -63,7 +122,8 @@ impl Board {
         );
 
         Self {
-            scd30: Scd30::init(twim),
+            scd30,
+            i2c,
             serial: uarte,
         }
     }
@@ -74,6 +134,160 @@ impl Board {
     }
 }
 
+/// A busy-waiting delay provider based on the cycle counter
+pub struct Delay;
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        let start = Instant::now();
+        let dur = Duration::from_micros(us.into());
+        while start.elapsed() < dur {}
+    }
+}
+
+/// The I2C bus `TWIM0` and a flag that marks it as in use by a transfer
+struct SharedTwim {
+    busy: AtomicBool,
+    twim: UnsafeCell<Twim<TWIM0>>,
+}
+
+// NOTE(unsafe) `twim` is only accessed while `busy` is held; see `I2c::transfer`
+unsafe impl Sync for SharedTwim {}
+
+/// A handle to the I2C bus `TWIM0`, which can be shared by several device drivers.
+/// Copy the handle to give each driver its own. Each transfer has exclusive access to the bus,
+/// without masking interrupts: a transfer started while another one is in progress, e.g. from
+/// a higher priority task, fails with `I2cError::Busy`
+#[derive(Clone, Copy)]
+pub struct I2c(&'static SharedTwim);
+
+impl I2c {
+    fn transfer<R>(
+        &mut self,
+        f: impl FnOnce(&mut Twim<TWIM0>) -> Result<R, twim::Error>,
+    ) -> Result<R, I2cError> {
+        if self
+            .0
+            .busy
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(I2cError::Busy);
+        }
+
+        // NOTE(unsafe) `busy` was clear, so there's no other reference to `twim`
+        let res = f(unsafe { &mut *self.0.twim.get() });
+        self.0.busy.store(false, Ordering::Release);
+        res.map_err(I2cError::Twim)
+    }
+}
+
+/// Error raised by an `I2c` transfer
+#[derive(Debug)]
+pub enum I2cError {
+    /// The bus is in use by another transfer
+    Busy,
+    Twim(twim::Error),
+}
+
+/// An error raised by the bus that connects the SCD30
+pub trait BusError {
+    /// Returns `true` if the bus was in use by another transfer, which may be retried later
+    fn is_busy(&self) -> bool;
+}
+
+impl BusError for I2cError {
+    fn is_busy(&self) -> bool {
+        matches!(self, I2cError::Busy)
+    }
+}
+
+#[cfg(feature = "modbus")]
+impl BusError for uarte::Error {
+    fn is_busy(&self) -> bool {
+        // the UART is not shared
+        false
+    }
+}
+
+impl i2c::Write for I2c {
+    type Error = I2cError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        // NOTE the trait method, unlike the inherent one, accepts `bytes` located in flash
+        self.transfer(|twim| i2c::Write::write(twim, address, bytes))
+    }
+}
+
+impl i2c::Read for I2c {
+    type Error = I2cError;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.transfer(|twim| i2c::Read::read(twim, address, buffer))
+    }
+}
+
+impl i2c::WriteRead for I2c {
+    type Error = I2cError;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.transfer(|twim| twim.write_read(address, bytes, buffer))
+    }
+}
+
+/// Time, in microseconds, that `ModbusSerial::read` waits for a byte: a bit more than it takes
+/// to transmit one at 19200 baud
+#[cfg(feature = "modbus")]
+const MODBUS_BYTE_TIMEOUT_US: u32 = 600;
+
+/// The serial port connected to the SCD30's Modbus interface
+#[cfg(feature = "modbus")]
+pub struct ModbusSerial {
+    uarte: Uarte<UARTE1>,
+    /// Bounds the wait for a byte
+    timer: Timer<TIMER1>,
+}
+
+#[cfg(feature = "modbus")]
+impl serial::Read<u8> for ModbusSerial {
+    type Error = uarte::Error;
+
+    // NOTE this waits up to `MODBUS_BYTE_TIMEOUT_US` for a byte and reports `WouldBlock` if none
+    // arrives; the UARTE has no way to tell if a byte is pending without starting a reception
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let mut byte = 0;
+        match self.uarte.read_timeout(
+            slice::from_mut(&mut byte),
+            &mut self.timer,
+            MODBUS_BYTE_TIMEOUT_US,
+        ) {
+            // the byte arrived just as the reception was cancelled
+            Ok(()) | Err(uarte::Error::Timeout(1)) => Ok(byte),
+            Err(uarte::Error::Timeout(_)) => Err(nb::Error::WouldBlock),
+            Err(e) => Err(nb::Error::Other(e)),
+        }
+    }
+}
+
+#[cfg(feature = "modbus")]
+impl blocking::serial::Write<u8> for ModbusSerial {
+    type Error = uarte::Error;
+
+    fn bwrite_all(&mut self, buffer: &[u8]) -> Result<(), Self::Error> {
+        self.uarte.write(buffer)
+    }
+
+    fn bflush(&mut self) -> Result<(), Self::Error> {
+        // `Uarte::write` returns once the transfer is complete
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Instant(u32);
 
@@ -93,3 +307,13 @@ impl Instant {
         self.0
     }
 }
+
+impl scd30::Instant for Instant {
+    fn now() -> Self {
+        Instant::now()
+    }
+
+    fn elapsed(&self) -> Duration {
+        Instant::elapsed(self)
+    }
+}
@@ -1,7 +1,15 @@
-use std::{thread, time::Duration};
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    thread,
+    time::Duration,
+};
 
 use anyhow::anyhow;
-use messages::{Host2Target, Measurement, Target2Host};
+use messages::{
+    Capabilities, Celsius, ConfigKey, ConfigValue, DeviceInfo, ErrorCode, FullMeasurement,
+    Host2Target, Measurement, Ppm, Request, RequestId, Response, Target2Host, PROTOCOL_VERSION,
+};
 use parking_lot::{Mutex, MutexGuard};
 use serialport::SerialPort;
 
@@ -14,6 +22,76 @@ fn get_measurement_succeeds() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+#[test]
+fn device_info_matches_host() -> Result<(), anyhow::Error> {
+    let mut target = TargetSerialConn::open()?;
+    let info = dbg!(target.get_device_info()?);
+    assert_eq!(PROTOCOL_VERSION, info.protocol_version);
+    assert_eq!(Some([3, 66]), info.scd30_firmware_version);
+    Ok(())
+}
+
+#[test]
+fn full_measurement_is_in_range() -> Result<(), anyhow::Error> {
+    let mut target = TargetSerialConn::open()?;
+
+    thread::sleep(Duration::from_millis(2_100));
+    let measurement = dbg!(target.get_full_measurement()?.unwrap());
+    assert!(measurement.co2.is_in_range());
+    assert!(measurement.temperature.is_in_range());
+    assert!(measurement.humidity.is_in_range());
+
+    let metrics = dbg!(target.get_derived_metrics(&measurement, 1_013)?);
+    assert!(metrics.dew_point <= measurement.temperature);
+    Ok(())
+}
+
+#[test]
+fn remote_configuration() -> Result<(), anyhow::Error> {
+    let mut target = TargetSerialConn::open()?;
+
+    // NOTE these are the values that the app configures; the other tests depend on them
+    assert_eq!(
+        ConfigValue::MeasurementInterval(2),
+        target.set_config(ConfigValue::MeasurementInterval(2))?
+    );
+    assert_eq!(
+        ConfigValue::AmbientPressure(1_020),
+        target.set_config(ConfigValue::AmbientPressure(1_020))?
+    );
+    dbg!(target.get_config(ConfigKey::TemperatureOffset)?);
+    dbg!(target.get_config(ConfigKey::AutomaticSelfCalibration)?);
+
+    // out of range; the sensor keeps the old interval
+    let err = target
+        .set_config(ConfigValue::MeasurementInterval(1))
+        .unwrap_err();
+    assert_eq!(
+        Some(&TargetError(ErrorCode::InvalidArgument)),
+        err.downcast_ref::<TargetError>()
+    );
+    assert_eq!(
+        ConfigValue::MeasurementInterval(2),
+        target.get_config(ConfigKey::MeasurementInterval)?
+    );
+    Ok(())
+}
+
+#[test]
+fn malformed_frame_gets_error_response() -> Result<(), anyhow::Error> {
+    let mut target = TargetSerialConn::open()?;
+
+    // COBS says that a 0 follows the second byte, but the frame ends before
+    target.port.write_all(&[0x05, 0x01, 0x00])?;
+    let resp = target.receive().map_err(|e| anyhow!("{:?}", e))?;
+    assert_eq!(RequestId::UNKNOWN, resp.id);
+    assert!(matches!(
+        resp.message,
+        Target2Host::Error(ErrorCode::MalformedFrame)
+    ));
+    Ok(())
+}
+
 #[test]
 fn new_measurement_every_2_seconds() -> Result<(), anyhow::Error> {
     let mut target = TargetSerialConn::open()?;
@@ -58,14 +136,18 @@ fn consecutive_sampling_returns_same_measurement() -> Result<(), anyhow::Error>
 }
 
 /// A connection between the host and the target over a serial interface
-pub struct TargetSerialConn {
-    port: Box<dyn SerialPort>,
+pub struct TargetSerialConn<P = Box<dyn SerialPort>> {
+    port: P,
     rx_bytes: Vec<u8>,
-    _guard: MutexGuard<'static, ()>,
+    next_request_id: u32,
+    /// Reported by the target during the handshake
+    device_info: Option<DeviceInfo>,
+    _guard: Option<MutexGuard<'static, ()>>,
 }
 
 impl TargetSerialConn {
-    /// Opens a serial connection to the target
+    /// Opens a serial connection to the target and checks that it speaks a compatible
+    /// protocol version
     // NOTE this operation does NOT use a lock file so a different process is free to operate on
     // the serial port (e.g. `[sudo] cat /dev/ttyACM0`). That can make the rest of this
     // API misbehave.
@@ -84,54 +166,219 @@ impl TargetSerialConn {
                     let port = serialport::new(port.port_name, BAUD_RATE)
                         .timeout(Duration::from_millis(100))
                         .open()?;
-                    return Ok(Self {
-                        port,
-                        rx_bytes: vec![],
-                        _guard,
-                    });
+                    let mut conn = Self {
+                        _guard: Some(_guard),
+                        ..Self::with_port(port)
+                    };
+                    conn.handshake()?;
+                    return Ok(conn);
                 }
             }
         }
 
         Err(anyhow!("device {:04x}:{:04x} is not connected", VID, PID))
     }
+}
+
+impl<P> TargetSerialConn<P>
+where
+    P: Read + Write,
+{
+    /// Talks to the target over `port`, which must time out reads that get no data
+    fn with_port(port: P) -> Self {
+        Self {
+            port,
+            rx_bytes: vec![],
+            next_request_id: 0,
+            device_info: None,
+            _guard: None,
+        }
+    }
+
+    /// Requests the device info and returns an error if the target's protocol version is not
+    /// compatible with the host's
+    fn handshake(&mut self) -> Result<DeviceInfo, anyhow::Error> {
+        let info = self.get_device_info().map_err(|e| {
+            anyhow!(
+                "target did not report its device info ({}); its firmware may predate protocol \
+                 versioning",
+                e
+            )
+        })?;
+
+        if !PROTOCOL_VERSION.is_compatible_with(info.protocol_version) {
+            return Err(anyhow!(
+                "target speaks protocol version {}.{} but the host speaks version {}.{} \
+                 (firmware {}.{}.{}, build {:08x}); flash a compatible firmware",
+                info.protocol_version.major,
+                info.protocol_version.minor,
+                PROTOCOL_VERSION.major,
+                PROTOCOL_VERSION.minor,
+                info.firmware_version.major,
+                info.firmware_version.minor,
+                info.firmware_version.patch,
+                info.build_id,
+            ));
+        }
+
+        self.device_info = Some(info);
+        Ok(info)
+    }
+
+    /// Requests the device info
+    pub fn get_device_info(&mut self) -> Result<DeviceInfo, anyhow::Error> {
+        match self.request(Host2Target::GetDeviceInfo)? {
+            Target2Host::DeviceInfo(info) => Ok(info),
+            resp => Err(anyhow!("unexpected response {:?}", resp)),
+        }
+    }
 
     /// Requests the last measurement
     pub fn get_measurement(&mut self) -> Result<Option<Measurement>, anyhow::Error> {
-        let resp = self.request(&Host2Target::GetLastMeasurement)?;
+        let resp = self.request(Host2Target::GetLastMeasurement)?;
 
         Ok(match resp {
             Target2Host::NotReady => None,
             Target2Host::Measurement(measurement) => Some(measurement),
+            resp => return Err(anyhow!("unexpected response {:?}", resp)),
         })
     }
 
+    /// Requests the last measurement, including temperature and humidity
+    pub fn get_full_measurement(&mut self) -> Result<Option<FullMeasurement>, anyhow::Error> {
+        self.require(Capabilities::FULL_MEASUREMENT, "report full measurements")?;
+
+        let resp = self.request(Host2Target::GetLastFullMeasurement)?;
+
+        Ok(match resp {
+            Target2Host::NotReady => None,
+            Target2Host::FullMeasurement(measurement) => Some(measurement),
+            resp => return Err(anyhow!("unexpected response {:?}", resp)),
+        })
+    }
+
+    /// Requests the value of a sensor setting
+    pub fn get_config(&mut self, key: ConfigKey) -> Result<ConfigValue, anyhow::Error> {
+        self.require(Capabilities::REMOTE_CONFIG, "support remote configuration")?;
+
+        match self.request(Host2Target::GetConfig(key))? {
+            Target2Host::Config(value) if value.key() == key => Ok(value),
+            resp => Err(anyhow!("unexpected response {:?}", resp)),
+        }
+    }
+
+    /// Derives metrics from `measurement`. `pressure` is the ambient pressure, in millibar, used
+    /// to normalise the CO2 concentration if the sensor's pressure compensation is disabled
+    pub fn get_derived_metrics(
+        &mut self,
+        measurement: &FullMeasurement,
+        pressure: u16,
+    ) -> Result<DerivedMetrics, anyhow::Error> {
+        let configurable = self
+            .device_info
+            .is_some_and(|info| info.capabilities.contains(Capabilities::REMOTE_CONFIG));
+        // NOTE firmware that can't be configured always enables pressure compensation
+        let compensated = !configurable
+            || self.get_config(ConfigKey::AmbientPressure)? != ConfigValue::AmbientPressure(0);
+
+        Ok(DerivedMetrics::new(
+            measurement,
+            if compensated { None } else { Some(pressure) },
+        ))
+    }
+
+    /// Changes a sensor setting.
+    /// Returns the value that the sensor applied, or an error if the target rejected the change
+    /// or kept a different value
+    pub fn set_config(&mut self, value: ConfigValue) -> Result<ConfigValue, anyhow::Error> {
+        self.require(Capabilities::REMOTE_CONFIG, "support remote configuration")?;
+
+        match self.request(Host2Target::SetConfig(value))? {
+            Target2Host::Config(applied) if applied == value => Ok(applied),
+            Target2Host::Config(applied) if applied.key() == value.key() => Err(anyhow!(
+                "target kept {:?} instead of applying {:?}",
+                applied,
+                value
+            )),
+            resp => Err(anyhow!("unexpected response {:?}", resp)),
+        }
+    }
+
+    /// Returns an error if the target reported, during the handshake, that it lacks
+    /// `capability`; `what` describes the capability in the error message
+    fn require(&self, capability: Capabilities, what: &str) -> Result<(), anyhow::Error> {
+        if let Some(info) = &self.device_info {
+            if !info.capabilities.contains(capability) {
+                return Err(anyhow!(
+                    "target firmware ({}.{}.{}) doesn't {}",
+                    info.firmware_version.major,
+                    info.firmware_version.minor,
+                    info.firmware_version.patch,
+                    what,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Sends a request to the target and waits for a response.
-    /// Returns the target response.
-    fn request(&mut self, request: &Host2Target) -> Result<Target2Host, anyhow::Error> {
+    /// Returns the target response, or a `TargetError` if the target reported an error.
+    // NOTE frames that don't answer this request, e.g. a late response to a request that timed
+    // out, or frames that can't be decoded are skipped
+    fn request(&mut self, message: Host2Target) -> Result<Target2Host, anyhow::Error> {
+        let id = RequestId(self.next_request_id);
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+        if RequestId(self.next_request_id) == RequestId::UNKNOWN {
+            self.next_request_id = 0;
+        }
+
+        let request = Request { id, message };
         let tx_bytes =
             postcard::to_allocvec_cobs(&request).map_err(|e| anyhow::Error::msg(e.to_string()))?;
 
         self.port.write_all(dbg!(&tx_bytes))?;
 
+        loop {
+            match self.receive() {
+                Ok(Response {
+                    id: resp_id,
+                    message: Target2Host::Error(code),
+                }) if resp_id == id || resp_id == RequestId::UNKNOWN => {
+                    return Err(TargetError(code).into())
+                }
+                Ok(resp) if resp.id == id => return Ok(resp.message),
+                Ok(resp) => {
+                    dbg!(("skipping response to another request", resp));
+                }
+                Err(ReceiveError::Io(e)) => return Err(e.into()),
+                Err(ReceiveError::Postcard(e)) => {
+                    dbg!(("skipping undecodable frame", e));
+                }
+            }
+        }
+    }
+
+    /// Waits for the next frame from the target and decodes it
+    fn receive(&mut self) -> Result<Response, ReceiveError> {
         let mut buffer = [0; 64];
 
         let delimiter_pos = loop {
-            let bytes_read = self.port.read(&mut buffer)?;
-
-            self.rx_bytes.extend_from_slice(&buffer[..bytes_read]);
-
             if let Some(pos) = self.rx_bytes.iter().position(|byte| *byte == 0) {
                 break pos;
             }
+
+            let bytes_read = self.port.read(&mut buffer)?;
+
+            self.rx_bytes.extend_from_slice(&buffer[..bytes_read]);
         };
 
         dbg!(&self.rx_bytes);
 
         let endpos = delimiter_pos + 1;
         let frame = &mut self.rx_bytes[..dbg!(endpos)];
-        let res = postcard::from_bytes_cobs::<Target2Host>(dbg!(frame))
-            .map_err(|e| anyhow::Error::msg(e.to_string()));
+        let res =
+            postcard::from_bytes_cobs::<Response>(dbg!(frame)).map_err(ReceiveError::Postcard);
 
         // pop frame from RX buffer *before* raising any error
         let len = self.rx_bytes.len();
@@ -143,3 +390,436 @@ impl TargetSerialConn {
         res
     }
 }
+
+/// Metrics that the host derives from a `FullMeasurement`, so that the target doesn't have to
+/// compute and send them
+#[derive(Clone, Copy, Debug)]
+pub struct DerivedMetrics {
+    pub dew_point: Celsius,
+    /// Absolute humidity, in g/m³
+    pub absolute_humidity: f32,
+    pub co2_at_standard_pressure: Ppm,
+}
+
+impl DerivedMetrics {
+    /// `uncompensated_pressure` is the ambient pressure, in millibar, at which `measurement` was
+    /// taken if the sensor's pressure compensation is disabled; `None` if it's enabled, in which
+    /// case the CO2 concentration needs no normalisation
+    pub fn new(measurement: &FullMeasurement, uncompensated_pressure: Option<u16>) -> Self {
+        let temperature = measurement.temperature;
+        let humidity = measurement.humidity;
+        DerivedMetrics {
+            dew_point: scd30::psychrometrics::dew_point(temperature, humidity),
+            absolute_humidity: scd30::psychrometrics::absolute_humidity(temperature, humidity),
+            co2_at_standard_pressure: uncompensated_pressure.map_or(measurement.co2, |pressure| {
+                scd30::psychrometrics::co2_at_standard_pressure(measurement.co2, pressure)
+            }),
+        }
+    }
+}
+
+/// An error reported by the target in a `Target2Host::Error` response
+#[derive(Debug, PartialEq)]
+pub struct TargetError(pub ErrorCode);
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "target reported an error: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for TargetError {}
+
+#[derive(Debug)]
+enum ReceiveError {
+    Io(io::Error),
+    Postcard(postcard::Error),
+}
+
+impl From<io::Error> for ReceiveError {
+    fn from(e: io::Error) -> Self {
+        ReceiveError::Io(e)
+    }
+}
+
+/// Tests of the framing, request correlation and version handshake that run against
+/// `FakeTarget` instead of the real target
+mod protocol {
+    use std::{collections::VecDeque, io};
+
+    use messages::{
+        Capabilities, Celsius, ConfigKey, ConfigValue, DeviceInfo, ErrorCode, FirmwareVersion,
+        FullMeasurement, Measurement, Ppm, ProtocolVersion, RelativeHumidity, RequestId, Response,
+        Target2Host, PROTOCOL_VERSION,
+    };
+
+    use super::{DerivedMetrics, TargetError, TargetSerialConn};
+
+    /// The serial port of a target that has queued up `rx_bytes` for the host
+    #[derive(Default)]
+    struct FakeTarget {
+        rx_bytes: VecDeque<u8>,
+        tx_bytes: Vec<u8>,
+    }
+
+    impl FakeTarget {
+        fn respond(&mut self, id: u32, message: Target2Host) {
+            let frame = postcard::to_allocvec_cobs(&Response {
+                id: RequestId(id),
+                message,
+            })
+            .unwrap();
+            self.rx_bytes.extend(frame);
+        }
+    }
+
+    impl io::Read for FakeTarget {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.rx_bytes.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+
+            let len = buf.len().min(self.rx_bytes.len());
+            for (dst, src) in buf.iter_mut().zip(self.rx_bytes.drain(..len)) {
+                *dst = src;
+            }
+            Ok(len)
+        }
+    }
+
+    impl io::Write for FakeTarget {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.tx_bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn device_info(protocol_version: ProtocolVersion) -> DeviceInfo {
+        DeviceInfo {
+            protocol_version,
+            firmware_version: FirmwareVersion {
+                major: 0,
+                minor: 1,
+                patch: 0,
+            },
+            build_id: 0x219a_be9d,
+            scd30_firmware_version: Some([3, 66]),
+            capabilities: Capabilities::LAST_MEASUREMENT,
+        }
+    }
+
+    fn measurement(id: u32) -> Measurement {
+        Measurement {
+            id,
+            timestamp: id * 2,
+            co2: Ppm(439.),
+        }
+    }
+
+    #[test]
+    fn response_id_matches_request_id() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        for id in 0..3 {
+            conn.port
+                .respond(id, Target2Host::Measurement(measurement(id)));
+            assert_eq!(Some(measurement(id)), conn.get_measurement()?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn dropped_response() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        // the response to request 0 is lost
+        assert!(conn.get_measurement().is_err());
+
+        conn.port.respond(1, Target2Host::NotReady);
+        assert_eq!(None, conn.get_measurement()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn late_response_is_skipped() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        // the response to request 0 arrives after the host gave up on it
+        assert!(conn.get_measurement().is_err());
+        conn.port
+            .respond(0, Target2Host::Measurement(measurement(0)));
+        conn.port.respond(1, Target2Host::NotReady);
+
+        assert_eq!(None, conn.get_measurement()?);
+        assert!(conn.port.rx_bytes.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn interleaved_frames() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        // a single read returns the response to request 0, a stale response and the start of
+        // the response to request 1
+        conn.port
+            .respond(0, Target2Host::Measurement(measurement(0)));
+        conn.port.respond(7, Target2Host::NotReady);
+        conn.port
+            .respond(1, Target2Host::Measurement(measurement(1)));
+        let partial = conn.port.rx_bytes.split_off(conn.port.rx_bytes.len() - 3);
+
+        assert_eq!(Some(measurement(0)), conn.get_measurement()?);
+
+        conn.port.rx_bytes.extend(partial);
+        assert_eq!(Some(measurement(1)), conn.get_measurement()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn corrupted_frame_is_skipped() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        // a frame that lost some of its bytes
+        conn.port
+            .respond(0, Target2Host::Measurement(measurement(0)));
+        let frame = conn.port.rx_bytes.drain(..).collect::<Vec<_>>();
+        conn.port.rx_bytes.extend(&frame[..2]);
+        conn.port.rx_bytes.extend(&frame[frame.len() - 2..]);
+        conn.port.respond(0, Target2Host::NotReady);
+
+        assert_eq!(None, conn.get_measurement()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_with_compatible_target() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        let newer = ProtocolVersion {
+            minor: PROTOCOL_VERSION.minor + 1,
+            ..PROTOCOL_VERSION
+        };
+        conn.port
+            .respond(0, Target2Host::DeviceInfo(device_info(newer)));
+        assert_eq!(device_info(newer), conn.handshake()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_refuses_incompatible_target() {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        let incompatible = ProtocolVersion {
+            major: PROTOCOL_VERSION.major + 1,
+            minor: 0,
+        };
+        conn.port
+            .respond(0, Target2Host::DeviceInfo(device_info(incompatible)));
+        let error = conn.handshake().unwrap_err().to_string();
+        assert!(error.contains("protocol version 2.0"), "{}", error);
+    }
+
+    #[test]
+    fn handshake_with_unversioned_target() {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        // firmware that predates `GetDeviceInfo` doesn't answer it
+        let error = conn.handshake().unwrap_err().to_string();
+        assert!(error.contains("predate"), "{}", error);
+    }
+
+    #[test]
+    fn full_measurement() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        let full = FullMeasurement {
+            id: 0,
+            timestamp: 0,
+            co2: Ppm(439.),
+            temperature: Celsius(25.),
+            humidity: RelativeHumidity(50.),
+        };
+        conn.port.respond(0, Target2Host::FullMeasurement(full));
+        let measurement = conn.get_full_measurement()?.unwrap();
+        assert_eq!(full, measurement);
+
+        let metrics = DerivedMetrics::new(&measurement, None);
+        assert!((metrics.dew_point.0 - 13.85).abs() < 0.01);
+        assert!((metrics.absolute_humidity - 11.5).abs() < 0.05);
+        assert_eq!(Ppm(439.), metrics.co2_at_standard_pressure);
+
+        Ok(())
+    }
+
+    #[test]
+    fn co2_is_normalised_without_pressure_compensation() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+        conn.device_info = Some(DeviceInfo {
+            capabilities: Capabilities::FULL_MEASUREMENT | Capabilities::REMOTE_CONFIG,
+            ..device_info(PROTOCOL_VERSION)
+        });
+
+        let measurement = FullMeasurement {
+            id: 0,
+            timestamp: 0,
+            co2: Ppm(800.),
+            temperature: Celsius(20.),
+            humidity: RelativeHumidity(40.),
+        };
+        conn.port
+            .respond(0, Target2Host::Config(ConfigValue::AmbientPressure(0)));
+        let metrics = conn.get_derived_metrics(&measurement, 800)?;
+        assert!((metrics.co2_at_standard_pressure.0 - 1_013.25).abs() < 0.01);
+
+        // with pressure compensation the reading is used as is
+        conn.port
+            .respond(1, Target2Host::Config(ConfigValue::AmbientPressure(1_020)));
+        let metrics = conn.get_derived_metrics(&measurement, 800)?;
+        assert_eq!(Ppm(800.), metrics.co2_at_standard_pressure);
+
+        Ok(())
+    }
+
+    #[test]
+    fn full_measurement_requires_capability() {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        let info = DeviceInfo {
+            protocol_version: ProtocolVersion { major: 1, minor: 0 },
+            ..device_info(PROTOCOL_VERSION)
+        };
+        conn.port.respond(0, Target2Host::DeviceInfo(info));
+        conn.handshake().unwrap();
+
+        // no request is sent to a target that doesn't support it
+        let tx_len = conn.port.tx_bytes.len();
+        assert!(conn.get_full_measurement().is_err());
+        assert_eq!(tx_len, conn.port.tx_bytes.len());
+    }
+
+    #[test]
+    fn config_is_acknowledged() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        conn.port
+            .respond(0, Target2Host::Config(ConfigValue::TemperatureOffset(150)));
+        assert_eq!(
+            ConfigValue::TemperatureOffset(150),
+            conn.get_config(ConfigKey::TemperatureOffset)?
+        );
+
+        conn.port
+            .respond(1, Target2Host::Config(ConfigValue::AmbientPressure(980)));
+        assert_eq!(
+            ConfigValue::AmbientPressure(980),
+            conn.set_config(ConfigValue::AmbientPressure(980))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejected_config_is_reported() {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        conn.port
+            .respond(0, Target2Host::Error(ErrorCode::InvalidArgument));
+        let error = conn
+            .set_config(ConfigValue::MeasurementInterval(1))
+            .unwrap_err();
+        assert_eq!(
+            Some(&TargetError(ErrorCode::InvalidArgument)),
+            error.downcast_ref::<TargetError>()
+        );
+    }
+
+    #[test]
+    fn config_that_was_not_applied_is_reported() {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        // the target acknowledged the change but kept its measurement interval
+        conn.port
+            .respond(0, Target2Host::Config(ConfigValue::MeasurementInterval(2)));
+        let error = conn
+            .set_config(ConfigValue::MeasurementInterval(1))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("kept MeasurementInterval(2)"), "{}", error);
+    }
+
+    #[test]
+    fn config_for_another_setting_is_rejected() {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        conn.port.respond(
+            0,
+            Target2Host::Config(ConfigValue::AutomaticSelfCalibration(true)),
+        );
+        assert!(conn.get_config(ConfigKey::AmbientPressure).is_err());
+    }
+
+    #[test]
+    fn error_response() {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        conn.port
+            .respond(0, Target2Host::Error(ErrorCode::SensorCrc));
+        let error = conn.get_config(ConfigKey::MeasurementInterval).unwrap_err();
+        assert_eq!(
+            Some(&TargetError(ErrorCode::SensorCrc)),
+            error.downcast_ref::<TargetError>()
+        );
+    }
+
+    #[test]
+    fn error_response_to_undecodable_request() {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        // the target couldn't tell which request the frame was
+        conn.port.respond(
+            RequestId::UNKNOWN.0,
+            Target2Host::Error(ErrorCode::MalformedFrame),
+        );
+        let error = conn.get_measurement().unwrap_err();
+        assert_eq!(
+            Some(&TargetError(ErrorCode::MalformedFrame)),
+            error.downcast_ref::<TargetError>()
+        );
+    }
+
+    #[test]
+    fn error_response_to_another_request_is_skipped() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+
+        conn.port
+            .respond(7, Target2Host::Error(ErrorCode::UnknownRequest));
+        conn.port.respond(0, Target2Host::NotReady);
+        assert_eq!(None, conn.get_measurement()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn request_ids_skip_unknown() -> Result<(), anyhow::Error> {
+        let mut conn = TargetSerialConn::with_port(FakeTarget::default());
+        conn.next_request_id = RequestId::UNKNOWN.0 - 1;
+
+        conn.port
+            .respond(RequestId::UNKNOWN.0 - 1, Target2Host::NotReady);
+        assert_eq!(None, conn.get_measurement()?);
+        conn.port.respond(0, Target2Host::NotReady);
+        assert_eq!(None, conn.get_measurement()?);
+
+        Ok(())
+    }
+}
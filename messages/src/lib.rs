@@ -1,18 +1,112 @@
 #![cfg_attr(not(test), no_std)]
 
+use core::ops::BitOr;
+
+// NOTE the sensor driver's units, with their conversions and range checks, are used as is; each
+// one is serialized as a plain `f32`
+pub use scd30::{Celsius, Ppm, RelativeHumidity};
 use serde_derive::{Deserialize, Serialize};
 
+/// Version of the protocol described by this crate
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 1, minor: 3 };
+
+/// A frame sent from the host to the target
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Request {
+    /// Chosen by the host; the target echoes it in its `Response`
+    pub id: RequestId,
+    pub message: Host2Target,
+}
+
+impl Request {
+    /// Decodes a COBS encoded frame, without its delimiter, in place.
+    /// On failure, returns the `Target2Host::Error` response that the target should send
+    pub fn decode(frame: &mut [u8]) -> Result<Request, Response> {
+        let error = |id, code| Response {
+            id,
+            message: Target2Host::Error(code),
+        };
+
+        let malformed = error(RequestId::UNKNOWN, ErrorCode::MalformedFrame);
+        let len = postcard_cobs::decode_in_place(frame).map_err(|()| malformed)?;
+        let (id, message) =
+            postcard::take_from_bytes::<RequestId>(&frame[..len]).map_err(|_| malformed)?;
+        match postcard::from_bytes::<Host2Target>(message) {
+            Ok(message) => Ok(Request { id, message }),
+            // an enum variant that this crate doesn't know, e.g. a request added in a newer
+            // protocol version
+            Err(postcard::Error::SerdeDeCustom) => Err(error(id, ErrorCode::UnknownRequest)),
+            Err(_) => Err(error(id, ErrorCode::MalformedFrame)),
+        }
+    }
+}
+
+/// A frame sent from the target to the host, in reply to the `Request` with the same `id`
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Response {
+    pub id: RequestId,
+    pub message: Target2Host,
+}
+
+/// Correlates a `Response` with its `Request`.
+/// The host should not reuse an identifier while a response to it may still be in flight
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RequestId(pub u32);
+
+impl RequestId {
+    /// Used in the `Target2Host::Error` response to a frame whose request ID couldn't be
+    /// decoded; the host must not use it in requests
+    pub const UNKNOWN: RequestId = RequestId(u32::MAX);
+}
+
 /// A message sent from the host to the target
+// NOTE new variants go at the end so that the encoding of the existing ones doesn't change
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum Host2Target {
     GetLastMeasurement,
+    GetDeviceInfo,
+    /// Since protocol version 1.1; see `Capabilities::FULL_MEASUREMENT`
+    GetLastFullMeasurement,
+    /// Since protocol version 1.2; see `Capabilities::REMOTE_CONFIG`
+    GetConfig(ConfigKey),
+    /// Since protocol version 1.2; see `Capabilities::REMOTE_CONFIG`
+    SetConfig(ConfigValue),
 }
 
 /// A message sent from the target to the host
+// NOTE new variants go at the end so that the encoding of the existing ones doesn't change
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum Target2Host {
     NotReady,
     Measurement(Measurement),
+    DeviceInfo(DeviceInfo),
+    FullMeasurement(FullMeasurement),
+    /// Since protocol version 1.2. Answers a successful `GetConfig` or `SetConfig` with the
+    /// value of the setting that is in effect; a failed one is answered with `Error`
+    Config(ConfigValue),
+    /// Since protocol version 1.3. The target couldn't handle the request
+    Error(ErrorCode),
+}
+
+/// Why the target couldn't handle a request
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ErrorCode {
+    /// The frame is not a valid COBS encoded `Request`, or it doesn't fit in the target's
+    /// receive buffer
+    MalformedFrame,
+    /// The request is not known to the target, which speaks an older protocol version
+    UnknownRequest,
+    /// The bus to the sensor reported an error; I2C, or UART with the Modbus interface
+    SensorBus,
+    /// A response from the sensor failed its CRC check
+    SensorCrc,
+    /// The sensor sent a response that doesn't make sense, e.g. a measurement out of range
+    SensorResponse,
+    /// The sensor driver rejected an argument of the request
+    InvalidArgument,
+    /// The target can't handle the request right now, e.g. because another device is using the
+    /// sensor bus; the host may retry it later
+    Busy,
 }
 
 /// A measurement reported by the target
@@ -22,44 +116,426 @@ pub struct Measurement {
     pub id: u32,
     /// A timestamp in unspecified units; it may wrap around
     pub timestamp: u32,
-    /// The CO2 concentration in parts per million (ppm)
-    pub co2: f32,
+    /// The CO2 concentration
+    pub co2: Ppm,
+}
+
+/// A measurement with every value reported by the sensor
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct FullMeasurement {
+    /// The measurement identifier; this is a monotonically increasing counter
+    pub id: u32,
+    /// A timestamp in unspecified units; it may wrap around
+    pub timestamp: u32,
+    /// The CO2 concentration
+    pub co2: Ppm,
+    /// The temperature
+    pub temperature: Celsius,
+    /// The relative humidity
+    pub humidity: RelativeHumidity,
+}
+
+impl From<FullMeasurement> for Measurement {
+    fn from(full: FullMeasurement) -> Self {
+        Measurement {
+            id: full.id,
+            timestamp: full.timestamp,
+            co2: full.co2,
+        }
+    }
+}
+
+/// A sensor setting that can be configured remotely
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ConfigKey {
+    MeasurementInterval,
+    AmbientPressure,
+    TemperatureOffset,
+    AutomaticSelfCalibration,
+}
+
+/// The value of a sensor setting
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ConfigValue {
+    /// Interval, in seconds, between measurements
+    MeasurementInterval(u16),
+    /// Ambient pressure, in millibar, used for pressure compensation; 0 disables it
+    AmbientPressure(u16),
+    /// Temperature offset, in units of 0.01 K
+    TemperatureOffset(u16),
+    /// Whether automatic self-calibration (ASC) is enabled
+    AutomaticSelfCalibration(bool),
+}
+
+impl ConfigValue {
+    /// Returns the setting that this is a value of
+    pub fn key(self) -> ConfigKey {
+        match self {
+            ConfigValue::MeasurementInterval(_) => ConfigKey::MeasurementInterval,
+            ConfigValue::AmbientPressure(_) => ConfigKey::AmbientPressure,
+            ConfigValue::TemperatureOffset(_) => ConfigKey::TemperatureOffset,
+            ConfigValue::AutomaticSelfCalibration(_) => ConfigKey::AutomaticSelfCalibration,
+        }
+    }
+}
+
+/// Describes the target, in response to `Host2Target::GetDeviceInfo`
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct DeviceInfo {
+    /// The protocol version that the target speaks
+    pub protocol_version: ProtocolVersion,
+    /// The version of the target firmware
+    pub firmware_version: FirmwareVersion,
+    /// Identifies the build of the target firmware, e.g. an abbreviated commit hash
+    pub build_id: u32,
+    /// The firmware version reported by the SCD30; `None` if the sensor didn't respond
+    pub scd30_firmware_version: Option<[u8; 2]>,
+    /// The optional requests that the target supports
+    pub capabilities: Capabilities,
+}
+
+/// A protocol version.
+/// The `major` version is bumped on incompatible changes, the `minor` version when messages
+/// are added; `Capabilities` tell which of them a target supports
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ProtocolVersion {
+    /// Returns `true` if a peer that speaks this version can talk to one that speaks `other`
+    pub fn is_compatible_with(self, other: ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+}
+
+/// A semantic version of the target firmware
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+/// A set of optional protocol features, as a bitmap
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    /// No optional features
+    pub const NONE: Capabilities = Capabilities(0);
+    /// `Host2Target::GetLastMeasurement`
+    pub const LAST_MEASUREMENT: Capabilities = Capabilities(1 << 0);
+    /// `Host2Target::GetLastFullMeasurement`
+    pub const FULL_MEASUREMENT: Capabilities = Capabilities(1 << 1);
+    /// `Host2Target::GetConfig` and `Host2Target::SetConfig`
+    pub const REMOTE_CONFIG: Capabilities = Capabilities(1 << 2);
+
+    /// Returns `true` if every feature in `other` is in this set
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Capabilities) -> Capabilities {
+        Capabilities(self.0 | rhs.0)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use quickcheck_macros::quickcheck;
 
-    use super::{Host2Target, Measurement, Target2Host};
+    use super::{
+        Capabilities, Celsius, ConfigKey, ConfigValue, DeviceInfo, ErrorCode, FirmwareVersion,
+        FullMeasurement, Host2Target, Measurement, Ppm, ProtocolVersion, RelativeHumidity, Request,
+        RequestId, Response, Target2Host, PROTOCOL_VERSION,
+    };
 
     /// Max payload size for a USB (2.0 Full Size) HID packet
     const MAX_SIZE: usize = 64;
 
-    #[test]
-    fn host2target_message_size() -> postcard::Result<()> {
-        let msg = Host2Target::GetLastMeasurement;
-        let bytes = postcard::to_allocvec(&msg)?;
-        assert!(dbg!(bytes).len() <= MAX_SIZE);
+    #[quickcheck]
+    fn host2target_message_size(request_id: u32) -> postcard::Result<()> {
+        let msg = Request {
+            id: RequestId(request_id),
+            message: Host2Target::GetLastMeasurement,
+        };
+        let bytes = postcard::to_allocvec_cobs(&msg)?;
+        assert!(bytes.len() <= MAX_SIZE);
         Ok(())
     }
 
-    #[test]
-    fn target2host_not_ready_message_size() -> postcard::Result<()> {
-        let msg = Target2Host::NotReady;
-        let bytes = postcard::to_allocvec(&msg)?;
-        assert!(dbg!(bytes).len() <= MAX_SIZE);
+    #[quickcheck]
+    fn target2host_not_ready_message_size(request_id: u32) -> postcard::Result<()> {
+        let msg = Response {
+            id: RequestId(request_id),
+            message: Target2Host::NotReady,
+        };
+        let bytes = postcard::to_allocvec_cobs(&msg)?;
+        assert!(bytes.len() <= MAX_SIZE);
         Ok(())
     }
 
     #[quickcheck]
     fn target2host_measurement_message_size(
+        request_id: u32,
         id: u32,
         timestamp: u32,
         co2: f32,
     ) -> postcard::Result<()> {
-        let msg = Target2Host::Measurement(Measurement { id, timestamp, co2 });
-        let bytes = postcard::to_allocvec(&msg)?;
+        let msg = Response {
+            id: RequestId(request_id),
+            message: Target2Host::Measurement(Measurement {
+                id,
+                timestamp,
+                co2: Ppm(co2),
+            }),
+        };
+        let bytes = postcard::to_allocvec_cobs(&msg)?;
+        assert!(bytes.len() <= MAX_SIZE);
+        Ok(())
+    }
+
+    #[quickcheck]
+    fn target2host_full_measurement_message_size(
+        request_id: u32,
+        id: u32,
+        timestamp: u32,
+        co2: f32,
+        temperature: f32,
+        humidity: f32,
+    ) -> postcard::Result<()> {
+        let msg = Response {
+            id: RequestId(request_id),
+            message: Target2Host::FullMeasurement(FullMeasurement {
+                id,
+                timestamp,
+                co2: Ppm(co2),
+                temperature: Celsius(temperature),
+                humidity: RelativeHumidity(humidity),
+            }),
+        };
+        let bytes = postcard::to_allocvec_cobs(&msg)?;
+        assert!(bytes.len() <= MAX_SIZE);
+        Ok(())
+    }
+
+    #[quickcheck]
+    fn config_message_size(request_id: u32, value: u16, enabled: bool) -> postcard::Result<()> {
+        let values = [
+            ConfigValue::MeasurementInterval(value),
+            ConfigValue::AmbientPressure(value),
+            ConfigValue::TemperatureOffset(value),
+            ConfigValue::AutomaticSelfCalibration(enabled),
+        ];
+        for &value in &values {
+            let requests = [
+                Host2Target::GetConfig(value.key()),
+                Host2Target::SetConfig(value),
+            ];
+            for &message in &requests {
+                let msg = Request {
+                    id: RequestId(request_id),
+                    message,
+                };
+                assert!(postcard::to_allocvec_cobs(&msg)?.len() <= MAX_SIZE);
+            }
+
+            let msg = Response {
+                id: RequestId(request_id),
+                message: Target2Host::Config(value),
+            };
+            assert!(postcard::to_allocvec_cobs(&msg)?.len() <= MAX_SIZE);
+        }
+        Ok(())
+    }
+
+    #[quickcheck]
+    fn target2host_error_message_size(request_id: u32) -> postcard::Result<()> {
+        let codes = [
+            ErrorCode::MalformedFrame,
+            ErrorCode::UnknownRequest,
+            ErrorCode::SensorBus,
+            ErrorCode::SensorCrc,
+            ErrorCode::SensorResponse,
+            ErrorCode::InvalidArgument,
+            ErrorCode::Busy,
+        ];
+        for &code in &codes {
+            let msg = Response {
+                id: RequestId(request_id),
+                message: Target2Host::Error(code),
+            };
+            assert!(postcard::to_allocvec_cobs(&msg)?.len() <= MAX_SIZE);
+        }
+        Ok(())
+    }
+
+    /// Returns the error code and request ID of a failed `Request::decode`
+    fn decode_error(frame: &mut [u8]) -> (RequestId, ErrorCode) {
+        match Request::decode(frame) {
+            Ok(request) => panic!("decoded {:?}", request),
+            Err(Response {
+                id,
+                message: Target2Host::Error(code),
+            }) => (id, code),
+            Err(resp) => panic!("unexpected response {:?}", resp),
+        }
+    }
+
+    #[quickcheck]
+    fn decode_request(request_id: u32) -> postcard::Result<()> {
+        let msg = Request {
+            id: RequestId(request_id),
+            message: Host2Target::GetConfig(ConfigKey::TemperatureOffset),
+        };
+        let mut frame = postcard::to_allocvec_cobs(&msg)?;
+        frame.pop(); // delimiter
+        let request = Request::decode(&mut frame).unwrap();
+        assert_eq!(RequestId(request_id), request.id);
+        assert!(matches!(
+            request.message,
+            Host2Target::GetConfig(ConfigKey::TemperatureOffset)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn decode_malformed_frame() {
+        // COBS says that a 0 follows the second byte, but the frame ends before
+        assert_eq!(
+            (RequestId::UNKNOWN, ErrorCode::MalformedFrame),
+            decode_error(&mut [0x05, 0x01])
+        );
+        // too short for a request ID
+        assert_eq!(
+            (RequestId::UNKNOWN, ErrorCode::MalformedFrame),
+            decode_error(&mut [0x03, 0x01, 0x02])
+        );
+        // request ID 1 but no message
+        assert_eq!(
+            (RequestId(1), ErrorCode::MalformedFrame),
+            decode_error(&mut [0x02, 0x01, 0x01, 0x01, 0x01])
+        );
+    }
+
+    #[test]
+    fn decode_unknown_request() {
+        // request ID 1 followed by a message variant that doesn't exist (yet)
+        assert_eq!(
+            (RequestId(1), ErrorCode::UnknownRequest),
+            decode_error(&mut [0x02, 0x01, 0x01, 0x01, 0x02, 0x7f])
+        );
+    }
+
+    #[test]
+    fn config_value_key() {
+        assert_eq!(
+            ConfigKey::AmbientPressure,
+            ConfigValue::AmbientPressure(1_020).key()
+        );
+        assert_eq!(
+            ConfigKey::AutomaticSelfCalibration,
+            ConfigValue::AutomaticSelfCalibration(false).key()
+        );
+    }
+
+    #[test]
+    fn existing_messages_keep_their_encoding() -> postcard::Result<()> {
+        assert_eq!(
+            vec![0],
+            postcard::to_allocvec(&Host2Target::GetLastMeasurement)?
+        );
+        assert_eq!(vec![1], postcard::to_allocvec(&Host2Target::GetDeviceInfo)?);
+        assert_eq!(vec![0], postcard::to_allocvec(&Target2Host::NotReady)?);
+        let measurement = Measurement {
+            id: 1,
+            timestamp: 2,
+            co2: Ppm(1.),
+        };
+        assert_eq!(
+            vec![1, 1, 0, 0, 0, 2, 0, 0, 0, 0x00, 0x00, 0x80, 0x3f],
+            postcard::to_allocvec(&Target2Host::Measurement(measurement))?
+        );
+        Ok(())
+    }
+
+    #[quickcheck]
+    fn units_serialize_as_f32(value: f32) -> postcard::Result<()> {
+        let bytes = postcard::to_allocvec(&value)?;
+        assert_eq!(bytes, postcard::to_allocvec(&Ppm(value))?);
+        assert_eq!(bytes, postcard::to_allocvec(&Celsius(value))?);
+        assert_eq!(bytes, postcard::to_allocvec(&RelativeHumidity(value))?);
+        Ok(())
+    }
+
+    #[quickcheck]
+    fn target2host_device_info_message_size(
+        request_id: u32,
+        protocol_version: (u16, u16),
+        firmware_version: (u8, u8, u8),
+        build_id: u32,
+        scd30_firmware_version: Option<(u8, u8)>,
+        capabilities: u32,
+    ) -> postcard::Result<()> {
+        let msg = Response {
+            id: RequestId(request_id),
+            message: Target2Host::DeviceInfo(DeviceInfo {
+                protocol_version: ProtocolVersion {
+                    major: protocol_version.0,
+                    minor: protocol_version.1,
+                },
+                firmware_version: FirmwareVersion {
+                    major: firmware_version.0,
+                    minor: firmware_version.1,
+                    patch: firmware_version.2,
+                },
+                build_id,
+                scd30_firmware_version: scd30_firmware_version.map(|(major, minor)| [major, minor]),
+                capabilities: Capabilities(capabilities),
+            }),
+        };
+        let bytes = postcard::to_allocvec_cobs(&msg)?;
         assert!(bytes.len() <= MAX_SIZE);
         Ok(())
     }
+
+    #[test]
+    fn protocol_version_compatibility() {
+        let minor_bump = ProtocolVersion {
+            minor: PROTOCOL_VERSION.minor + 1,
+            ..PROTOCOL_VERSION
+        };
+        let major_bump = ProtocolVersion {
+            major: PROTOCOL_VERSION.major + 1,
+            ..PROTOCOL_VERSION
+        };
+        assert!(PROTOCOL_VERSION.is_compatible_with(minor_bump));
+        assert!(!PROTOCOL_VERSION.is_compatible_with(major_bump));
+    }
+
+    #[test]
+    fn capabilities() {
+        let all = Capabilities::LAST_MEASUREMENT | Capabilities::FULL_MEASUREMENT;
+        assert!(all.contains(Capabilities::LAST_MEASUREMENT));
+        assert!(all.contains(Capabilities::NONE));
+        assert!(!Capabilities::NONE.contains(Capabilities::LAST_MEASUREMENT));
+    }
+
+    #[quickcheck]
+    fn response_id_round_trips(request_id: u32) -> postcard::Result<()> {
+        let msg = Response {
+            id: RequestId(request_id),
+            message: Target2Host::NotReady,
+        };
+        let mut bytes = postcard::to_allocvec_cobs(&msg)?;
+        let decoded = postcard::from_bytes_cobs::<Response>(&mut bytes)?;
+        assert_eq!(RequestId(request_id), decoded.id);
+        Ok(())
+    }
 }
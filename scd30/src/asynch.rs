@@ -0,0 +1,458 @@
+//! Async SCD30 driver built on the embedded-hal-async traits
+
+use embedded_hal_async::{delay::DelayNs, i2c::I2c};
+
+use crate::{
+    check_ambient_pressure, check_argument, check_measurement, decode_measurement, decode_status,
+    decode_words, encode_command_with_argument, Altitude, Command, Error, SensorData,
+    TemperatureOffset, ADDRESS, FORCED_RECALIBRATION_RANGE, MAX_RESPONSE_LEN, MAX_RESPONSE_WORDS,
+    MEASUREMENT_INTERVAL_RANGE, READ_DELAY_US, WORD_LEN,
+};
+
+/// A SCD30 sensor on the async I2C bus `I`.
+/// The delay provider `D` is used to pause between a command and its response
+pub struct Scd30<I, D>
+where
+    I: I2c,
+    D: DelayNs,
+{
+    i2c: I,
+    delay: D,
+    /// Whether responses are checked for unexpected status words and invalid measurements
+    validate_responses: bool,
+}
+
+impl<I, D> Scd30<I, D>
+where
+    I: I2c,
+    D: DelayNs,
+{
+    /// Initializes the SCD30 driver.
+    /// This consumes the I2C bus `I` and the delay provider `D`
+    pub fn init(i2c: I, delay: D) -> Self {
+        Scd30 {
+            i2c,
+            delay,
+            validate_responses: true,
+        }
+    }
+
+    /// Enables or disables the validation of the sensor's responses: status words must be 0 or 1
+    /// and measurements must be finite and within the sensor's measurement range.
+    /// Validation is enabled by default
+    pub fn set_response_validation(&mut self, enabled: bool) {
+        self.validate_responses = enabled;
+    }
+
+    /// Returns the firmware version reported by the SCD30 sensor
+    pub async fn get_firmware_version(&mut self) -> Result<[u8; 2], Error<I::Error>> {
+        self.read_word(Command::FirmwareVersion.i2c_code())
+            .await
+            .map(u16::to_be_bytes)
+    }
+
+    /// Starts continuous measurement with ambient pressure compensation.
+    /// `ambient_pressure` is given in millibar and must be in the range 700..=1400;
+    /// use 0 to disable pressure compensation
+    pub async fn start_continuous_measurement(
+        &mut self,
+        ambient_pressure: u16,
+    ) -> Result<(), Error<I::Error>> {
+        check_ambient_pressure(ambient_pressure)?;
+        self.write_command_with_argument(
+            Command::StartContinuousMeasurement.i2c_code(),
+            ambient_pressure,
+        )
+        .await
+    }
+
+    /// Updates the ambient pressure compensation while continuous measurement is running.
+    /// The sensor applies the new value by restarting continuous measurement; the same
+    /// range as in `start_continuous_measurement` applies
+    pub async fn set_ambient_pressure(
+        &mut self,
+        ambient_pressure: u16,
+    ) -> Result<(), Error<I::Error>> {
+        self.start_continuous_measurement(ambient_pressure).await
+    }
+
+    /// Stops continuous measurement
+    pub async fn stop_continuous_measurement(&mut self) -> Result<(), Error<I::Error>> {
+        self.write_command(Command::StopContinuousMeasurement.i2c_code())
+            .await
+    }
+
+    /// Restarts the sensor without clearing its persisted configuration.
+    /// NOTE the sensor needs up to 2 seconds to boot after this command
+    pub async fn soft_reset(&mut self) -> Result<(), Error<I::Error>> {
+        self.write_command(Command::SoftReset.i2c_code()).await
+    }
+
+    /// Sets the interval, in seconds, between continuous measurements.
+    /// `interval` must be in the range 2..=1800
+    pub async fn set_measurement_interval(&mut self, interval: u16) -> Result<(), Error<I::Error>> {
+        check_argument(MEASUREMENT_INTERVAL_RANGE, interval)?;
+        self.write_command_with_argument(Command::MeasurementInterval.i2c_code(), interval)
+            .await
+    }
+
+    /// Returns the interval, in seconds, between continuous measurements
+    pub async fn get_measurement_interval(&mut self) -> Result<u16, Error<I::Error>> {
+        self.read_word(Command::MeasurementInterval.i2c_code())
+            .await
+    }
+
+    /// Enables or disables automatic self-calibration (ASC)
+    pub async fn set_automatic_self_calibration(
+        &mut self,
+        enabled: bool,
+    ) -> Result<(), Error<I::Error>> {
+        self.write_command_with_argument(
+            Command::AutomaticSelfCalibration.i2c_code(),
+            u16::from(enabled),
+        )
+        .await
+    }
+
+    /// Returns whether automatic self-calibration (ASC) is enabled
+    pub async fn get_automatic_self_calibration(&mut self) -> Result<bool, Error<I::Error>> {
+        let status = self
+            .read_word(Command::AutomaticSelfCalibration.i2c_code())
+            .await?;
+        decode_status(status, self.validate_responses)
+    }
+
+    /// Recalibrates the sensor against a reference CO2 concentration, in ppm.
+    /// `co2` must be in the range 400..=2000
+    pub async fn set_forced_recalibration_value(
+        &mut self,
+        co2: u16,
+    ) -> Result<(), Error<I::Error>> {
+        check_argument(FORCED_RECALIBRATION_RANGE, co2)?;
+        self.write_command_with_argument(Command::ForcedRecalibrationValue.i2c_code(), co2)
+            .await
+    }
+
+    /// Returns the reference CO2 concentration, in ppm, used by the last forced recalibration
+    pub async fn get_forced_recalibration_value(&mut self) -> Result<u16, Error<I::Error>> {
+        self.read_word(Command::ForcedRecalibrationValue.i2c_code())
+            .await
+    }
+
+    /// Sets the offset that the sensor subtracts from its temperature reading to compensate
+    /// for self-heating; this also affects the humidity reading
+    pub async fn set_temperature_offset(
+        &mut self,
+        offset: TemperatureOffset,
+    ) -> Result<(), Error<I::Error>> {
+        self.write_command_with_argument(
+            Command::TemperatureOffset.i2c_code(),
+            offset.as_centikelvin(),
+        )
+        .await
+    }
+
+    /// Returns the temperature offset applied by the sensor
+    pub async fn get_temperature_offset(&mut self) -> Result<TemperatureOffset, Error<I::Error>> {
+        self.read_word(Command::TemperatureOffset.i2c_code())
+            .await
+            .map(TemperatureOffset::from_centikelvin)
+    }
+
+    /// Sets the altitude used for compensation of the CO2 reading.
+    /// This setting is ignored while ambient pressure compensation is enabled
+    pub async fn set_altitude_compensation(
+        &mut self,
+        altitude: Altitude,
+    ) -> Result<(), Error<I::Error>> {
+        self.write_command_with_argument(
+            Command::AltitudeCompensation.i2c_code(),
+            altitude.as_meters(),
+        )
+        .await
+    }
+
+    /// Returns the altitude used for compensation of the CO2 reading
+    pub async fn get_altitude_compensation(&mut self) -> Result<Altitude, Error<I::Error>> {
+        self.read_word(Command::AltitudeCompensation.i2c_code())
+            .await
+            .map(Altitude::from_meters)
+    }
+
+    /// Returns `true` if a new measurement is available
+    pub async fn data_ready(&mut self) -> Result<bool, Error<I::Error>> {
+        let status = self.read_word(Command::DataReady.i2c_code()).await?;
+        decode_status(status, self.validate_responses)
+    }
+
+    /// Reads the last measurement
+    pub async fn read_measurement(&mut self) -> Result<SensorData, Error<I::Error>> {
+        let mut words = [0u16; MAX_RESPONSE_WORDS];
+        self.read_words(Command::ReadMeasurement.i2c_code(), &mut words)
+            .await?;
+
+        let data = decode_measurement(&words);
+        if self.validate_responses {
+            check_measurement(&data)?;
+        }
+        Ok(data)
+    }
+
+    /// Destroys this driver and releases the I2C bus `I` and the delay provider `D`
+    pub fn destroy(self) -> (I, D) {
+        (self.i2c, self.delay)
+    }
+
+    /// Sends a command that takes no argument
+    async fn write_command(&mut self, command: [u8; 2]) -> Result<(), Error<I::Error>> {
+        self.i2c.write(ADDRESS, &command).await.map_err(Error::I2c)
+    }
+
+    /// Sends a command that takes a 16-bit argument
+    async fn write_command_with_argument(
+        &mut self,
+        command: [u8; 2],
+        argument: u16,
+    ) -> Result<(), Error<I::Error>> {
+        let command = encode_command_with_argument(command, argument);
+        self.i2c.write(ADDRESS, &command).await.map_err(Error::I2c)
+    }
+
+    /// Sends a command and reads back its CRC-protected 16-bit response
+    async fn read_word(&mut self, command: [u8; 2]) -> Result<u16, Error<I::Error>> {
+        let mut word = [0u16; 1];
+        self.read_words(command, &mut word).await?;
+        Ok(word[0])
+    }
+
+    /// Sends a command and reads back `words.len()` CRC-protected 16-bit words
+    async fn read_words(
+        &mut self,
+        command: [u8; 2],
+        words: &mut [u16],
+    ) -> Result<(), Error<I::Error>> {
+        let mut rd_buffer = [0u8; MAX_RESPONSE_LEN];
+        let rd_buffer = &mut rd_buffer[..words.len() * WORD_LEN];
+
+        self.i2c
+            .write(ADDRESS, &command)
+            .await
+            .map_err(Error::I2c)?;
+        self.delay.delay_us(READ_DELAY_US).await;
+        self.i2c
+            .read(ADDRESS, rd_buffer)
+            .await
+            .map_err(Error::I2c)?;
+
+        decode_words(rd_buffer, words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::Future,
+        pin::Pin,
+        ptr,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    };
+
+    use embedded_hal_mock::eh1::{delay::NoopDelay, i2c};
+
+    use super::Scd30;
+    use crate::{Altitude, Error, TemperatureOffset, ADDRESS};
+
+    /// Polls `future` to completion. The mocks never return `Poll::Pending` so this executor
+    /// doesn't need to be woken up
+    fn block_on<F: Future>(future: F) -> F::Output {
+        const VTABLE: RawWakerVTable = RawWakerVTable::new(
+            |_| RawWaker::new(ptr::null(), &VTABLE),
+            |_| {},
+            |_| {},
+            |_| {},
+        );
+
+        let waker = unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) };
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            if let Poll::Ready(output) = Pin::as_mut(&mut future).poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    fn init(expectations: &[i2c::Transaction]) -> Scd30<i2c::Mock, NoopDelay> {
+        Scd30::init(i2c::Mock::new(expectations), NoopDelay::new())
+    }
+
+    fn done(scd30: Scd30<i2c::Mock, NoopDelay>) {
+        scd30.destroy().0.done();
+    }
+
+    #[test]
+    fn firmware_version() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
+            i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, 0xF3]),
+            i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
+            // NOTE negated CRC byte in the response!
+            i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, !0xF3]),
+        ];
+        let mut scd30 = init(&expectations);
+        assert_eq!(Ok([3, 66]), block_on(scd30.get_firmware_version()));
+        assert_eq!(
+            Err(Error::InvalidCrc),
+            block_on(scd30.get_firmware_version())
+        );
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn continuous_measurement() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x03, 0xFC, 0x53]),
+            i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x02, 0xBC, 0x9A]),
+            i2c::Transaction::write(ADDRESS, vec![0x01, 0x04]),
+            i2c::Transaction::write(ADDRESS, vec![0xD3, 0x04]),
+        ];
+        let mut scd30 = init(&expectations);
+        block_on(scd30.start_continuous_measurement(1_020)).unwrap();
+        block_on(scd30.set_ambient_pressure(700)).unwrap();
+        // NOTE out of range values never reach the bus
+        assert_eq!(
+            Err(Error::InvalidArgument),
+            block_on(scd30.start_continuous_measurement(1_401))
+        );
+        block_on(scd30.stop_continuous_measurement()).unwrap();
+        block_on(scd30.soft_reset()).unwrap();
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn measurement_interval() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x46, 0x00, 0x00, 0x02, 0xE3]),
+            i2c::Transaction::write(ADDRESS, vec![0x46, 0x00]),
+            i2c::Transaction::read(ADDRESS, vec![0x00, 0x05, 0x74]),
+        ];
+        let mut scd30 = init(&expectations);
+        block_on(scd30.set_measurement_interval(2)).unwrap();
+        assert_eq!(
+            Err(Error::InvalidArgument),
+            block_on(scd30.set_measurement_interval(1_801))
+        );
+        assert_eq!(Ok(5), block_on(scd30.get_measurement_interval()));
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn calibration() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x53, 0x06, 0x00, 0x01, 0xB0]),
+            i2c::Transaction::write(ADDRESS, vec![0x53, 0x06]),
+            i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+            i2c::Transaction::write(ADDRESS, vec![0x52, 0x04, 0x01, 0xC2, 0x50]),
+            i2c::Transaction::write(ADDRESS, vec![0x52, 0x04]),
+            i2c::Transaction::read(ADDRESS, vec![0x01, 0x90, 0x4C]),
+        ];
+        let mut scd30 = init(&expectations);
+        block_on(scd30.set_automatic_self_calibration(true)).unwrap();
+        assert_eq!(Ok(true), block_on(scd30.get_automatic_self_calibration()));
+        block_on(scd30.set_forced_recalibration_value(450)).unwrap();
+        assert_eq!(
+            Err(Error::InvalidArgument),
+            block_on(scd30.set_forced_recalibration_value(399))
+        );
+        assert_eq!(Ok(400), block_on(scd30.get_forced_recalibration_value()));
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn compensation() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x54, 0x03, 0x01, 0xF4, 0x33]),
+            i2c::Transaction::write(ADDRESS, vec![0x54, 0x03]),
+            i2c::Transaction::read(ADDRESS, vec![0x01, 0x54, 0xCF]),
+            i2c::Transaction::write(ADDRESS, vec![0x51, 0x02, 0x03, 0xE8, 0xD4]),
+            i2c::Transaction::write(ADDRESS, vec![0x51, 0x02]),
+            i2c::Transaction::read(ADDRESS, vec![0x03, 0xE8, 0xD4]),
+        ];
+        let mut scd30 = init(&expectations);
+        block_on(scd30.set_temperature_offset(TemperatureOffset::from_centikelvin(500))).unwrap();
+        assert_eq!(
+            Ok(TemperatureOffset::from_centikelvin(340)),
+            block_on(scd30.get_temperature_offset())
+        );
+        block_on(scd30.set_altitude_compensation(Altitude::from_meters(1_000))).unwrap();
+        assert_eq!(
+            Ok(Altitude::from_meters(1_000)),
+            block_on(scd30.get_altitude_compensation())
+        );
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn read_measurement() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+            i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+            i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+            // example response from the Interface Specification document
+            i2c::Transaction::read(
+                ADDRESS,
+                vec![
+                    0x43, 0xDB, 0xCB, 0x8C, 0x2E, 0x8F, // CO2 = 439 ppm
+                    0x41, 0xD9, 0x70, 0xE7, 0xFF, 0xF5, // temperature = 27.2 C
+                    0x42, 0x43, 0xBF, 0x3A, 0x1B, 0x74, // humidity = 48.8 %
+                ],
+            ),
+        ];
+        let mut scd30 = init(&expectations);
+        assert_eq!(Ok(true), block_on(scd30.data_ready()));
+        let data = block_on(scd30.read_measurement()).unwrap();
+        assert_eq!(439, data.co2.0 as u32);
+        assert_eq!(27, data.temperature.0 as u32);
+        assert_eq!(48, data.humidity.0 as u32);
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn response_validation() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+            i2c::Transaction::read(ADDRESS, vec![0x00, 0x02, 0xE3]),
+            i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+            i2c::Transaction::read(
+                ADDRESS,
+                vec![
+                    0x7F, 0xC0, 0x64, 0x00, 0x00, 0x81, // CO2 = NaN
+                    0x41, 0xD9, 0x70, 0xE7, 0xFF, 0xF5, // temperature = 27.2 C
+                    0x42, 0x43, 0xBF, 0x3A, 0x1B, 0x74, // humidity = 48.8 %
+                ],
+            ),
+            i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+            i2c::Transaction::read(ADDRESS, vec![0x00, 0x02, 0xE3]),
+        ];
+        let mut scd30 = init(&expectations);
+        assert_eq!(
+            Err(Error::UnexpectedStatus(2)),
+            block_on(scd30.data_ready())
+        );
+        assert!(matches!(
+            block_on(scd30.read_measurement()),
+            Err(Error::NonFiniteMeasurement)
+        ));
+
+        scd30.set_response_validation(false);
+        assert_eq!(Ok(false), block_on(scd30.data_ready()));
+
+        done(scd30); // verify expectations
+    }
+}
@@ -0,0 +1,40 @@
+//! Support for buses shared with other devices
+
+use embedded_hal::blocking::{delay::DelayUs, i2c};
+
+/// Lets the driver borrow an I2C bus or delay provider instead of owning it, e.g.
+/// `Scd30::init(ByRef(&mut i2c), ByRef(&mut delay))`.
+/// The bus can be used to talk to other devices once the driver is dropped. To keep several
+/// drivers alive at once, give each of them a shared-bus proxy instead
+pub struct ByRef<'a, T>(pub &'a mut T);
+
+impl<'a, I> i2c::Write for ByRef<'a, I>
+where
+    I: i2c::Write,
+{
+    type Error = I::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(address, bytes)
+    }
+}
+
+impl<'a, I> i2c::Read for ByRef<'a, I>
+where
+    I: i2c::Read,
+{
+    type Error = I::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(address, buffer)
+    }
+}
+
+impl<'a, D> DelayUs<u32> for ByRef<'a, D>
+where
+    D: DelayUs<u32>,
+{
+    fn delay_us(&mut self, us: u32) {
+        self.0.delay_us(us)
+    }
+}
@@ -0,0 +1,39 @@
+//! Support for the embedded-hal 1.0 traits
+
+use embedded_hal::blocking::{delay::DelayUs, i2c};
+use embedded_hal_1 as eh1;
+
+/// Adapts an embedded-hal 1.0 I2C bus or delay provider to the embedded-hal 0.2 traits
+/// that `Scd30` is built on, e.g. `Scd30::init(Eh1(i2c), Eh1(delay))`
+pub struct Eh1<T>(pub T);
+
+impl<I> i2c::Write for Eh1<I>
+where
+    I: eh1::i2c::I2c,
+{
+    type Error = I::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(address, bytes)
+    }
+}
+
+impl<I> i2c::Read for Eh1<I>
+where
+    I: eh1::i2c::I2c,
+{
+    type Error = I::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(address, buffer)
+    }
+}
+
+impl<D> DelayUs<u32> for Eh1<D>
+where
+    D: eh1::delay::DelayNs,
+{
+    fn delay_us(&mut self, us: u32) {
+        self.0.delay_us(us)
+    }
+}
@@ -0,0 +1,469 @@
+//! Fault injection for host-side tests
+//!
+//! `FaultyBus` wraps an I2C bus, usually a `sim::Simulator`, and corrupts the transfers that
+//! go through it, either on a schedule or at random
+
+use embedded_hal::blocking::i2c;
+
+/// A fault injected into a single transfer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// The device doesn't acknowledge the transfer; the inner bus is not used
+    Nack,
+    /// One bit of the transferred bytes is flipped; `bit` counts from the most significant
+    /// bit of the first byte and wraps around. Writes are corrupted before they reach the
+    /// inner bus, reads after
+    BitFlip { bit: usize },
+    /// Only the first `len` bytes are transferred; a truncated read returns 0xff, the level
+    /// of the idle bus, for the missing bytes
+    Truncate { len: usize },
+    /// The bus gets stuck: this and every later transfer fails until `FaultyBus::unstick`
+    Stuck,
+}
+
+/// Error raised by `FaultyBus`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultError<E> {
+    /// An injected NACK
+    Nack,
+    /// The bus is stuck
+    Stuck,
+    /// Error raised by the inner bus
+    Bus(E),
+}
+
+/// Wraps the I2C bus `I` and injects faults into its transfers
+pub struct FaultyBus<I> {
+    i2c: I,
+    /// Number of transfers so far
+    transfers: usize,
+    /// Faults to inject, with the index of the transfer they apply to
+    schedule: Vec<(usize, Fault)>,
+    /// Source of random faults and their probability, in percent
+    random: Option<(XorShift, u32)>,
+    stuck: bool,
+    /// Faults injected so far, with the index of the transfer they were applied to
+    injected: Vec<(usize, Fault)>,
+}
+
+impl<I> FaultyBus<I> {
+    /// Wraps `i2c`; no faults are injected until some are scheduled or enabled
+    pub fn new(i2c: I) -> Self {
+        FaultyBus {
+            i2c,
+            transfers: 0,
+            schedule: Vec::new(),
+            random: None,
+            stuck: false,
+            injected: Vec::new(),
+        }
+    }
+
+    /// Injects `fault` into the transfer with index `transfer`; the first transfer has
+    /// index 0. A command with a response takes 2 transfers: a write and a read
+    pub fn schedule(&mut self, transfer: usize, fault: Fault) {
+        self.schedule.push((transfer, fault));
+    }
+
+    /// Injects a random fault into each transfer with a probability of `percent`.
+    /// The same `seed` always produces the same faults
+    pub fn randomize(&mut self, seed: u64, percent: u32) {
+        self.random = Some((XorShift::new(seed), percent));
+    }
+
+    /// Frees a stuck bus
+    pub fn unstick(&mut self) {
+        self.stuck = false;
+    }
+
+    /// Returns the faults injected so far, with the index of the transfer they were applied to
+    pub fn injected(&self) -> &[(usize, Fault)] {
+        &self.injected
+    }
+
+    /// Releases the inner bus
+    pub fn release(self) -> I {
+        self.i2c
+    }
+
+    /// Picks the fault to inject into the next transfer, if any
+    fn next_fault(&mut self) -> Option<Fault> {
+        let transfer = self.transfers;
+        self.transfers += 1;
+
+        let scheduled = self
+            .schedule
+            .iter()
+            .position(|(index, _)| *index == transfer)
+            .map(|i| self.schedule.remove(i).1);
+        let fault = scheduled.or_else(|| {
+            let (rng, percent) = self.random.as_mut()?;
+            if rng.next() % 100 >= u64::from(*percent) {
+                return None;
+            }
+            let arg = rng.next() as usize;
+            Some(match rng.next() % 4 {
+                0 => Fault::Nack,
+                1 => Fault::BitFlip { bit: arg },
+                2 => Fault::Truncate { len: arg % 8 },
+                _ => Fault::Stuck,
+            })
+        });
+
+        if let Some(fault) = fault {
+            self.injected.push((transfer, fault));
+            if fault == Fault::Stuck {
+                self.stuck = true;
+            }
+        }
+        fault
+    }
+}
+
+impl<E, I> i2c::Write for FaultyBus<I>
+where
+    I: i2c::Write<Error = E>,
+{
+    type Error = FaultError<E>;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), FaultError<E>> {
+        let fault = self.next_fault();
+        if self.stuck {
+            return Err(FaultError::Stuck);
+        }
+
+        let mut corrupted = bytes.to_vec();
+        match fault {
+            None | Some(Fault::Stuck) => {}
+            Some(Fault::Nack) => return Err(FaultError::Nack),
+            Some(Fault::BitFlip { bit }) => flip(&mut corrupted, bit),
+            Some(Fault::Truncate { len }) => corrupted.truncate(len),
+        }
+
+        self.i2c.write(address, &corrupted).map_err(FaultError::Bus)
+    }
+}
+
+impl<E, I> i2c::Read for FaultyBus<I>
+where
+    I: i2c::Read<Error = E>,
+{
+    type Error = FaultError<E>;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), FaultError<E>> {
+        let fault = self.next_fault();
+        if self.stuck {
+            return Err(FaultError::Stuck);
+        }
+        if fault == Some(Fault::Nack) {
+            return Err(FaultError::Nack);
+        }
+
+        self.i2c.read(address, buffer).map_err(FaultError::Bus)?;
+        match fault {
+            Some(Fault::BitFlip { bit }) => flip(buffer, bit),
+            Some(Fault::Truncate { len }) => {
+                for byte in buffer.iter_mut().skip(len) {
+                    *byte = 0xff;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Flips bit `bit`, modulo the number of bits, of `bytes`
+fn flip(bytes: &mut [u8], bit: usize) {
+    if bytes.is_empty() {
+        return;
+    }
+
+    let bit = bit % (bytes.len() * 8);
+    bytes[bit / 8] ^= 0x80 >> (bit % 8);
+}
+
+/// xorshift64 pseudo-random number generator
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // the state must not be zero
+        XorShift(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sim::{SimError, Simulator},
+        Altitude, Error, I2cTransport, Scd30, TemperatureOffset,
+    };
+
+    use super::{Fault, FaultError, FaultyBus};
+
+    type Driver = Scd30<I2cTransport<FaultyBus<Simulator>, Simulator>>;
+    type DriverError = Error<FaultError<SimError>>;
+
+    /// A driver method; the result value is discarded
+    type Method = fn(&mut Driver) -> Result<(), DriverError>;
+
+    /// The full command set: every driver method, with the length of the command it writes and
+    /// of the response it reads, if any
+    const METHODS: [(&str, Method, usize, usize); 17] = [
+        (
+            "start_continuous_measurement",
+            |scd30| scd30.start_continuous_measurement(1_020),
+            5,
+            0,
+        ),
+        (
+            "set_ambient_pressure",
+            |scd30| scd30.set_ambient_pressure(1_020),
+            5,
+            0,
+        ),
+        (
+            "stop_continuous_measurement",
+            |scd30| scd30.stop_continuous_measurement(),
+            2,
+            0,
+        ),
+        ("soft_reset", |scd30| scd30.soft_reset(), 2, 0),
+        (
+            "set_measurement_interval",
+            |scd30| scd30.set_measurement_interval(5),
+            5,
+            0,
+        ),
+        (
+            "get_measurement_interval",
+            |scd30| scd30.get_measurement_interval().map(drop),
+            2,
+            3,
+        ),
+        (
+            "set_automatic_self_calibration",
+            |scd30| scd30.set_automatic_self_calibration(true),
+            5,
+            0,
+        ),
+        (
+            "get_automatic_self_calibration",
+            |scd30| scd30.get_automatic_self_calibration().map(drop),
+            2,
+            3,
+        ),
+        (
+            "set_forced_recalibration_value",
+            |scd30| scd30.set_forced_recalibration_value(450),
+            5,
+            0,
+        ),
+        (
+            "get_forced_recalibration_value",
+            |scd30| scd30.get_forced_recalibration_value().map(drop),
+            2,
+            3,
+        ),
+        (
+            "set_temperature_offset",
+            |scd30| scd30.set_temperature_offset(TemperatureOffset::from_centikelvin(150)),
+            5,
+            0,
+        ),
+        (
+            "get_temperature_offset",
+            |scd30| scd30.get_temperature_offset().map(drop),
+            2,
+            3,
+        ),
+        (
+            "set_altitude_compensation",
+            |scd30| scd30.set_altitude_compensation(Altitude::from_meters(300)),
+            5,
+            0,
+        ),
+        (
+            "get_altitude_compensation",
+            |scd30| scd30.get_altitude_compensation().map(drop),
+            2,
+            3,
+        ),
+        (
+            "get_firmware_version",
+            |scd30| scd30.get_firmware_version().map(drop),
+            2,
+            3,
+        ),
+        ("data_ready", |scd30| scd30.data_ready().map(drop), 2, 3),
+        (
+            "read_measurement",
+            |scd30| scd30.read_measurement().map(drop),
+            2,
+            18,
+        ),
+    ];
+
+    fn init(faults: &[(usize, Fault)]) -> Driver {
+        let sim = Simulator::new();
+        let mut bus = FaultyBus::new(sim.clone());
+        for &(transfer, fault) in faults {
+            bus.schedule(transfer, fault);
+        }
+        Scd30::init(bus, sim)
+    }
+
+    /// Methods that read a response, with the length of the response
+    fn reads() -> impl Iterator<Item = (&'static str, Method, usize)> {
+        METHODS
+            .iter()
+            .filter(|(_, _, _, response_len)| *response_len != 0)
+            .map(|&(name, method, _, response_len)| (name, method, response_len))
+    }
+
+    #[test]
+    fn no_faults() {
+        let mut scd30 = init(&[]);
+        for (name, method, _, _) in METHODS.iter() {
+            assert_eq!(Ok(()), method(&mut scd30), "{}", name);
+        }
+    }
+
+    #[test]
+    fn nack() {
+        for (name, method, _, _) in METHODS.iter() {
+            let mut scd30 = init(&[(0, Fault::Nack)]);
+            assert_eq!(
+                Err(Error::I2c(FaultError::Nack)),
+                method(&mut scd30),
+                "{}",
+                name
+            );
+            // the next transfer goes through
+            assert_eq!(Ok(()), method(&mut scd30), "{}", name);
+        }
+
+        for (name, method, _) in reads() {
+            let mut scd30 = init(&[(1, Fault::Nack)]);
+            assert_eq!(
+                Err(Error::I2c(FaultError::Nack)),
+                method(&mut scd30),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn bit_flip_in_command() {
+        // the sensor rejects unknown commands and arguments with a bad CRC
+        for (name, method, command_len, _) in METHODS.iter() {
+            for bit in 0..command_len * 8 {
+                let mut scd30 = init(&[(0, Fault::BitFlip { bit })]);
+                assert_eq!(
+                    Err(Error::I2c(FaultError::Bus(SimError::Nack))),
+                    method(&mut scd30),
+                    "{}, bit {}",
+                    name,
+                    bit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bit_flip_in_response() {
+        for (name, method, len) in reads() {
+            for bit in 0..len * 8 {
+                let mut scd30 = init(&[(1, Fault::BitFlip { bit })]);
+                assert_eq!(
+                    Err(Error::InvalidCrc),
+                    method(&mut scd30),
+                    "{}, bit {}",
+                    name,
+                    bit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_response() {
+        for (name, method, len) in reads() {
+            for truncated in 0..len {
+                let mut scd30 = init(&[(1, Fault::Truncate { len: truncated })]);
+                assert_eq!(
+                    Err(Error::InvalidCrc),
+                    method(&mut scd30),
+                    "{}, {} bytes",
+                    name,
+                    truncated
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stuck_bus() {
+        let mut scd30 = init(&[(2, Fault::Stuck)]);
+        scd30.stop_continuous_measurement().unwrap();
+        scd30.soft_reset().unwrap();
+
+        for (name, method, _, _) in METHODS.iter() {
+            assert_eq!(
+                Err(Error::I2c(FaultError::Stuck)),
+                method(&mut scd30),
+                "{}",
+                name
+            );
+        }
+
+        let (mut bus, sim) = scd30.destroy();
+        bus.unstick();
+        let mut scd30 = Scd30::init(bus, sim);
+        for (name, method, _, _) in METHODS.iter() {
+            assert_eq!(Ok(()), method(&mut scd30), "{}", name);
+        }
+    }
+
+    #[test]
+    fn random_faults() {
+        for seed in 0..200 {
+            let sim = Simulator::new();
+            let mut bus = FaultyBus::new(sim.clone());
+            bus.randomize(seed, 30);
+            let mut scd30 = Scd30::init(bus, sim);
+
+            for (name, method, _, _) in METHODS.iter() {
+                match method(&mut scd30) {
+                    Ok(())
+                    | Err(Error::I2c(FaultError::Nack))
+                    | Err(Error::I2c(FaultError::Stuck))
+                    | Err(Error::I2c(FaultError::Bus(SimError::Nack)))
+                    | Err(Error::InvalidCrc) => {}
+                    Err(e) => panic!("{}: unexpected error {:?} (seed {})", name, e, seed),
+                }
+
+                let (mut bus, sim) = scd30.destroy();
+                bus.unstick();
+                scd30 = Scd30::init(bus, sim);
+            }
+
+            let (bus, _) = scd30.destroy();
+            assert!(
+                !bus.injected().is_empty(),
+                "seed {} injected no faults",
+                seed
+            );
+        }
+    }
+}
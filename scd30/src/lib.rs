@@ -1,124 +1,459 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "sim")), no_std)]
+
+use core::{fmt, ops::RangeInclusive, time::Duration};
 
 use crc_any::CRCu8;
-use defmt::Format;
-use embedded_hal::blocking::i2c;
+use embedded_hal::blocking::{delay::DelayUs, i2c};
+
+pub use bus::ByRef;
+#[cfg(feature = "eh1")]
+pub use eh1::Eh1;
+pub use modbus::ModbusTransport;
+pub use transport::{Command, I2cTransport, Transport};
+pub use units::{Celsius, Fahrenheit, Ppm, RelativeHumidity};
+
+/// Reports an anomaly through the `log` crate; a no-op without the `log` feature
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "log")]
+        log::warn!($($arg)*);
+    };
+}
 
-/// SCD30 I2C address
+#[cfg(feature = "async")]
+pub mod asynch;
+mod bus;
+#[cfg(feature = "eh1")]
+mod eh1;
+#[cfg(feature = "sim")]
+pub mod faults;
+mod modbus;
+pub mod psychrometrics;
+pub mod record;
+#[cfg(feature = "sim")]
+pub mod sim;
+mod transport;
+pub mod typestate;
+mod units;
+
+/// SCD30 I2C and Modbus address
 const ADDRESS: u8 = 0x61;
 
-#[derive(Clone, Copy, Format)]
+/// Minimum pause, in microseconds, between writing a command and reading its response
+const READ_DELAY_US: u32 = 3_000;
+
+/// Size of a response word: 2 data bytes followed by their CRC
+const WORD_LEN: usize = 3;
+
+/// Number of words in the largest sensor response: a measurement
+const MAX_RESPONSE_WORDS: usize = 6;
+
+/// Size of the largest sensor response
+const MAX_RESPONSE_LEN: usize = MAX_RESPONSE_WORDS * WORD_LEN;
+
+/// Ambient pressure range, in millibar, accepted by the sensor for pressure compensation
+const AMBIENT_PRESSURE_RANGE: RangeInclusive<u16> = 700..=1_400;
+
+/// Measurement interval range, in seconds, accepted by the sensor
+const MEASUREMENT_INTERVAL_RANGE: RangeInclusive<u16> = 2..=1_800;
+
+/// Forced recalibration reference range, in ppm, accepted by the sensor
+const FORCED_RECALIBRATION_RANGE: RangeInclusive<u16> = 400..=2_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct SensorData {
-    pub co2: f32,
-    pub temperature: f32,
-    pub humidity: f32,
+    pub co2: Ppm,
+    pub temperature: Celsius,
+    pub humidity: RelativeHumidity,
+}
+
+impl fmt::Display for SensorData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CO2: {}, temperature: {}, humidity: {}",
+            self.co2, self.temperature, self.humidity
+        )
+    }
+}
+
+/// A temperature offset with the sensor's resolution of 0.01 K
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TemperatureOffset(u16);
+
+impl TemperatureOffset {
+    /// Creates an offset from a value in hundredths of a Kelvin
+    pub fn from_centikelvin(centikelvin: u16) -> Self {
+        TemperatureOffset(centikelvin)
+    }
+
+    /// Creates an offset from a value in Kelvin, rounded to the nearest 0.01 K.
+    /// Returns `None` if the value is negative, not finite or too large to be represented
+    pub fn from_kelvin(kelvin: f32) -> Option<Self> {
+        let centikelvin = kelvin * 100. + 0.5;
+        if centikelvin >= 0. && centikelvin < f32::from(u16::MAX) + 1. {
+            Some(TemperatureOffset(centikelvin as u16))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the offset in hundredths of a Kelvin
+    pub fn as_centikelvin(self) -> u16 {
+        self.0
+    }
+
+    /// Returns the offset in Kelvin
+    pub fn as_kelvin(self) -> f32 {
+        f32::from(self.0) / 100.
+    }
 }
 
-/// A SCD30 sensor on the I2C bus `I`
-pub struct Scd30<I>(I)
+/// An altitude above sea level, in meters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Altitude(u16);
+
+impl Altitude {
+    /// Creates an altitude from a value in meters above sea level
+    pub fn from_meters(meters: u16) -> Self {
+        Altitude(meters)
+    }
+
+    /// Returns the altitude in meters above sea level
+    pub fn as_meters(self) -> u16 {
+        self.0
+    }
+}
+
+/// A point in time of a monotonic clock, e.g. `board::Instant`
+pub trait Instant: Copy {
+    /// Returns the current time
+    fn now() -> Self;
+
+    /// Returns the time elapsed since this instant
+    fn elapsed(&self) -> Duration;
+}
+
+/// A SCD30 sensor connected through the transport `T`
+pub struct Scd30<T>
 where
-    I: i2c::Read + i2c::Write;
+    T: Transport,
+{
+    transport: T,
+    /// Whether responses are checked for unexpected status words and invalid measurements
+    validate_responses: bool,
+}
 
 /// A driver error
 #[derive(Debug, PartialEq)]
 pub enum Error<E> {
     /// I2C bus error
     I2c(E),
+    /// Serial bus error
+    Serial(E),
     /// CRC validation failed
     InvalidCrc,
+    /// A command argument is outside the range accepted by the sensor
+    InvalidArgument,
+    /// The sensor's response is not a valid reply to the command
+    InvalidResponse,
+    /// A measured value is NaN or infinite
+    NonFiniteMeasurement,
+    /// A measured value is outside the sensor's measurement range
+    MeasurementOutOfRange,
+    /// A status word, like the data ready flag, is neither 0 nor 1
+    UnexpectedStatus(u16),
+    /// No new measurement became available in time, or the sensor did not respond in time
+    Timeout,
 }
 
-impl<E, I> Scd30<I>
+impl<E, I, D> Scd30<I2cTransport<I, D>>
 where
     I: i2c::Read<Error = E> + i2c::Write<Error = E>,
+    D: DelayUs<u32>,
 {
-    /// Initializes the SCD30 driver.
-    /// This consumes the I2C bus `I`
-    pub fn init(i2c: I) -> Self {
-        Scd30(i2c)
+    /// Initializes the SCD30 driver on the I2C bus `I`.
+    /// This consumes the I2C bus `I` and the delay provider `D`
+    pub fn init(i2c: I, delay: D) -> Self {
+        Scd30::with_transport(I2cTransport::new(i2c, delay))
+    }
+
+    /// Destroys this driver and releases the I2C bus `I` and the delay provider `D`
+    pub fn destroy(self) -> (I, D) {
+        self.transport.release()
+    }
+}
+
+impl<T> Scd30<T>
+where
+    T: Transport,
+{
+    /// Initializes the SCD30 driver on the given transport
+    pub fn with_transport(transport: T) -> Self {
+        Scd30 {
+            transport,
+            validate_responses: true,
+        }
+    }
+
+    /// Destroys this driver and releases its transport
+    pub fn into_transport(self) -> T {
+        self.transport
+    }
+
+    /// Enables or disables the validation of the sensor's responses: status words must be 0 or 1
+    /// and measurements must be finite and within the sensor's measurement range.
+    /// Validation is enabled by default
+    pub fn set_response_validation(&mut self, enabled: bool) {
+        self.validate_responses = enabled;
     }
 
     /// Returns the firmware version reported by the SCD30 sensor
-    pub fn get_firmware_version(&mut self) -> Result<[u8; 2], Error<E>> {
-        let command: [u8; 2] = [0xd1, 0x00];
-        let mut rd_buffer = [0u8; 3];
+    pub fn get_firmware_version(&mut self) -> Result<[u8; 2], Error<T::Error>> {
+        self.read_word(Command::FirmwareVersion)
+            .map(u16::to_be_bytes)
+    }
 
-        self.0.write(ADDRESS, &command).map_err(Error::I2c)?;
-        self.0.read(ADDRESS, &mut rd_buffer).map_err(Error::I2c)?;
+    /// Starts continuous measurement with ambient pressure compensation.
+    /// `ambient_pressure` is given in millibar and must be in the range 700..=1400;
+    /// use 0 to disable pressure compensation
+    pub fn start_continuous_measurement(
+        &mut self,
+        ambient_pressure: u16,
+    ) -> Result<(), Error<T::Error>> {
+        check_ambient_pressure(ambient_pressure)?;
+        self.transport
+            .write(Command::StartContinuousMeasurement, Some(ambient_pressure))
+    }
 
-        let major = rd_buffer[0];
-        let minor = rd_buffer[1];
-        let crc = rd_buffer[2];
+    /// Updates the ambient pressure compensation while continuous measurement is running.
+    /// The sensor applies the new value by restarting continuous measurement; the same
+    /// range as in `start_continuous_measurement` applies
+    pub fn set_ambient_pressure(&mut self, ambient_pressure: u16) -> Result<(), Error<T::Error>> {
+        self.start_continuous_measurement(ambient_pressure)
+    }
 
-        if compute_crc(&rd_buffer[..2]) == crc {
-            Ok([major, minor])
-        } else {
-            Err(Error::InvalidCrc)
-        }
+    /// Stops continuous measurement
+    pub fn stop_continuous_measurement(&mut self) -> Result<(), Error<T::Error>> {
+        self.transport
+            .write(Command::StopContinuousMeasurement, None)
     }
 
-    pub fn start_continuous_measurement(&mut self) -> Result<(), Error<E>> {
-        // NOTE should be configurable
-        const AMBIENT_PRESSURE: u16 = 1_020;
+    /// Restarts the sensor without clearing its persisted configuration.
+    /// NOTE the sensor needs up to 2 seconds to boot after this command
+    pub fn soft_reset(&mut self) -> Result<(), Error<T::Error>> {
+        self.transport.write(Command::SoftReset, None)
+    }
 
-        // command bytes
-        let mut command: [u8; 5] = [0x00, 0x10, 0x00, 0x00, 0x00];
-        let argument_bytes = &AMBIENT_PRESSURE.to_be_bytes();
+    /// Sets the interval, in seconds, between continuous measurements.
+    /// `interval` must be in the range 2..=1800
+    pub fn set_measurement_interval(&mut self, interval: u16) -> Result<(), Error<T::Error>> {
+        check_argument(MEASUREMENT_INTERVAL_RANGE, interval)?;
+        self.transport
+            .write(Command::MeasurementInterval, Some(interval))
+    }
 
-        command[2] = argument_bytes[0];
-        command[3] = argument_bytes[1];
-        command[4] = compute_crc(argument_bytes);
+    /// Returns the interval, in seconds, between continuous measurements
+    pub fn get_measurement_interval(&mut self) -> Result<u16, Error<T::Error>> {
+        self.read_word(Command::MeasurementInterval)
+    }
 
-        self.0.write(ADDRESS, &command).map_err(Error::I2c)?;
+    /// Enables or disables automatic self-calibration (ASC)
+    pub fn set_automatic_self_calibration(&mut self, enabled: bool) -> Result<(), Error<T::Error>> {
+        self.transport
+            .write(Command::AutomaticSelfCalibration, Some(u16::from(enabled)))
+    }
 
-        Ok(())
+    /// Returns whether automatic self-calibration (ASC) is enabled
+    pub fn get_automatic_self_calibration(&mut self) -> Result<bool, Error<T::Error>> {
+        let status = self.read_word(Command::AutomaticSelfCalibration)?;
+        decode_status(status, self.validate_responses)
+    }
+
+    /// Recalibrates the sensor against a reference CO2 concentration, in ppm.
+    /// `co2` must be in the range 400..=2000
+    pub fn set_forced_recalibration_value(&mut self, co2: u16) -> Result<(), Error<T::Error>> {
+        check_argument(FORCED_RECALIBRATION_RANGE, co2)?;
+        self.transport
+            .write(Command::ForcedRecalibrationValue, Some(co2))
     }
 
-    // NOTE testing these 3 methods is left as an exercise for the reader
-    pub fn data_ready(&mut self) -> Result<bool, Error<E>> {
-        let command: [u8; 2] = [0x02, 0x02];
-        let mut rd_buffer = [0u8; 3];
+    /// Returns the reference CO2 concentration, in ppm, used by the last forced recalibration
+    pub fn get_forced_recalibration_value(&mut self) -> Result<u16, Error<T::Error>> {
+        self.read_word(Command::ForcedRecalibrationValue)
+    }
 
-        self.0.write(ADDRESS, &command).map_err(Error::I2c)?;
-        self.0.read(ADDRESS, &mut rd_buffer).map_err(Error::I2c)?;
+    /// Sets the offset that the sensor subtracts from its temperature reading to compensate
+    /// for self-heating; this also affects the humidity reading
+    pub fn set_temperature_offset(
+        &mut self,
+        offset: TemperatureOffset,
+    ) -> Result<(), Error<T::Error>> {
+        self.transport
+            .write(Command::TemperatureOffset, Some(offset.as_centikelvin()))
+    }
 
-        Ok(u16::from_be_bytes([rd_buffer[0], rd_buffer[1]]) == 1)
+    /// Returns the temperature offset applied by the sensor
+    pub fn get_temperature_offset(&mut self) -> Result<TemperatureOffset, Error<T::Error>> {
+        self.read_word(Command::TemperatureOffset)
+            .map(TemperatureOffset::from_centikelvin)
     }
 
-    pub fn read_measurement(&mut self) -> Result<SensorData, Error<E>> {
-        let command: [u8; 2] = [0x03, 0x00];
-        let mut rd_buffer = [0u8; 18];
+    /// Sets the altitude used for compensation of the CO2 reading.
+    /// This setting is ignored while ambient pressure compensation is enabled
+    pub fn set_altitude_compensation(&mut self, altitude: Altitude) -> Result<(), Error<T::Error>> {
+        self.transport
+            .write(Command::AltitudeCompensation, Some(altitude.as_meters()))
+    }
 
-        self.0.write(ADDRESS, &command).map_err(Error::I2c)?;
-        self.0.read(ADDRESS, &mut rd_buffer).map_err(Error::I2c)?;
+    /// Returns the altitude used for compensation of the CO2 reading
+    pub fn get_altitude_compensation(&mut self) -> Result<Altitude, Error<T::Error>> {
+        self.read_word(Command::AltitudeCompensation)
+            .map(Altitude::from_meters)
+    }
 
-        let data = SensorData {
-            co2: f32::from_bits(u32::from_be_bytes([
-                rd_buffer[0],
-                rd_buffer[1],
-                rd_buffer[3],
-                rd_buffer[4],
-            ])),
-            temperature: f32::from_bits(u32::from_be_bytes([
-                rd_buffer[6],
-                rd_buffer[7],
-                rd_buffer[9],
-                rd_buffer[10],
-            ])),
-            humidity: f32::from_bits(u32::from_be_bytes([
-                rd_buffer[12],
-                rd_buffer[13],
-                rd_buffer[15],
-                rd_buffer[16],
-            ])),
-        };
+    /// Returns `true` if a new measurement is available
+    pub fn data_ready(&mut self) -> Result<bool, Error<T::Error>> {
+        let status = self.read_word(Command::DataReady)?;
+        decode_status(status, self.validate_responses)
+    }
+
+    /// Reads the last measurement
+    pub fn read_measurement(&mut self) -> Result<SensorData, Error<T::Error>> {
+        let mut words = [0u16; MAX_RESPONSE_WORDS];
+        self.transport.read(Command::ReadMeasurement, &mut words)?;
+
+        let data = decode_measurement(&words);
+        if self.validate_responses {
+            check_measurement(&data)?;
+        }
         Ok(data)
     }
 
-    /// Destroys this driver and releases the I2C bus `I`
-    pub fn destroy(self) -> I {
-        self.0
+    /// Polls the data ready flag every `poll_interval` until a new measurement is available,
+    /// then reads it. Gives up once `timeout`, as measured by the clock `C`, has elapsed;
+    /// `delay` provides the pause between polls
+    pub fn wait_for_measurement<C, D>(
+        &mut self,
+        delay: &mut D,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<SensorData, Error<T::Error>>
+    where
+        C: Instant,
+        D: DelayUs<u32>,
+    {
+        let poll_interval_us = poll_interval.as_micros().min(u32::MAX.into()) as u32;
+        let start = C::now();
+        loop {
+            if self.data_ready()? {
+                return self.read_measurement();
+            }
+            if start.elapsed() >= timeout {
+                warn!("SCD30: no measurement after {:?}", timeout);
+                return Err(Error::Timeout);
+            }
+            delay.delay_us(poll_interval_us);
+        }
+    }
+
+    /// Sends a command and reads back its 16-bit response
+    fn read_word(&mut self, command: Command) -> Result<u16, Error<T::Error>> {
+        let mut word = [0u16; 1];
+        self.transport.read(command, &mut word)?;
+        Ok(word[0])
+    }
+}
+
+/// Encodes an I2C command followed by its 16-bit argument and the argument's CRC
+fn encode_command_with_argument(command: [u8; 2], argument: u16) -> [u8; 5] {
+    let argument_bytes = argument.to_be_bytes();
+    [
+        command[0],
+        command[1],
+        argument_bytes[0],
+        argument_bytes[1],
+        compute_crc(&argument_bytes),
+    ]
+}
+
+/// Checks that a command argument is within `range`
+fn check_argument<E>(range: RangeInclusive<u16>, argument: u16) -> Result<(), Error<E>> {
+    if range.contains(&argument) {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument)
+    }
+}
+
+/// Checks an ambient pressure argument; 0 disables pressure compensation
+fn check_ambient_pressure<E>(ambient_pressure: u16) -> Result<(), Error<E>> {
+    if ambient_pressure == 0 {
+        Ok(())
+    } else {
+        check_argument(AMBIENT_PRESSURE_RANGE, ambient_pressure)
+    }
+}
+
+/// Decodes an I2C response made of 16-bit big endian words, each followed by its CRC
+fn decode_words<E>(bytes: &[u8], words: &mut [u16]) -> Result<(), Error<E>> {
+    for (chunk, word) in bytes.chunks_exact(WORD_LEN).zip(words) {
+        if compute_crc(&chunk[..2]) != chunk[2] {
+            warn!("SCD30: invalid CRC in response word {:02x?}", chunk);
+            return Err(Error::InvalidCrc);
+        }
+
+        *word = u16::from_be_bytes([chunk[0], chunk[1]]);
     }
+
+    Ok(())
+}
+
+/// Decodes the 6 words of a measurement response
+fn decode_measurement(words: &[u16; MAX_RESPONSE_WORDS]) -> SensorData {
+    SensorData {
+        co2: Ppm(words_to_f32(words[0], words[1])),
+        temperature: Celsius(words_to_f32(words[2], words[3])),
+        humidity: RelativeHumidity(words_to_f32(words[4], words[5])),
+    }
+}
+
+/// Decodes a status word, which the sensor only sets to 0 or 1.
+/// Without `validate`, any value other than 1 decodes as `false`
+fn decode_status<E>(word: u16, validate: bool) -> Result<bool, Error<E>> {
+    match word {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ if validate => {
+            warn!("SCD30: unexpected status word {:#06x}", word);
+            Err(Error::UnexpectedStatus(word))
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Checks that every measured value is finite and within the sensor's measurement range
+fn check_measurement<E>(data: &SensorData) -> Result<(), Error<E>> {
+    let values = [data.co2.0, data.temperature.0, data.humidity.0];
+    if values.iter().any(|value| !value.is_finite()) {
+        warn!("SCD30: non-finite measurement {}", data);
+        Err(Error::NonFiniteMeasurement)
+    } else if !(data.co2.is_in_range()
+        && data.temperature.is_in_range()
+        && data.humidity.is_in_range())
+    {
+        warn!("SCD30: measurement out of range {}", data);
+        Err(Error::MeasurementOutOfRange)
+    } else {
+        Ok(())
+    }
+}
+
+/// Combines the two words that the sensor uses to transfer a `f32` value
+fn words_to_f32(high: u16, low: u16) -> f32 {
+    f32::from_bits(u32::from(high) << 16 | u32::from(low))
 }
 
 fn compute_crc(bytes: &[u8]) -> u8 {
@@ -129,39 +464,706 @@ fn compute_crc(bytes: &[u8]) -> u8 {
 
 #[cfg(test)]
 mod tests {
-    use super::{Error, Scd30, ADDRESS};
-    use embedded_hal_mock::i2c;
+    use core::{cell::Cell, time::Duration};
 
-    #[test]
-    fn firmware_version() {
-        let expectations = vec![
-            i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
-            i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, 0xF3]),
-        ];
-        let mock = i2c::Mock::new(&expectations);
+    use embedded_hal::blocking::delay::DelayUs;
 
-        let mut scd30 = Scd30::init(mock);
-        let version = scd30.get_firmware_version().unwrap();
-        assert_eq!([3, 66], version);
+    use super::{Celsius, Instant, Ppm, RelativeHumidity, SensorData, TemperatureOffset};
 
-        let mut mock = scd30.destroy();
-        mock.done(); // verify expectations
+    thread_local! {
+        /// Current time, in microseconds, of `FakeInstant`
+        static NOW: Cell<u64> = const { Cell::new(0) };
     }
 
-    #[test]
-    fn firmware_version_bad_crc() {
-        let expectations = vec![
-            i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
-            // NOTE negated CRC byte in the response!
-            i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, !0xF3]),
-        ];
-        let mock = i2c::Mock::new(&expectations);
+    /// A clock that only advances when `FakeDelay` is used
+    #[derive(Clone, Copy)]
+    pub struct FakeInstant(u64);
+
+    impl Instant for FakeInstant {
+        fn now() -> Self {
+            FakeInstant(NOW.with(Cell::get))
+        }
+
+        fn elapsed(&self) -> Duration {
+            Duration::from_micros(NOW.with(Cell::get) - self.0)
+        }
+    }
+
+    /// A delay provider that advances the time of `FakeInstant`
+    pub struct FakeDelay;
+
+    impl DelayUs<u32> for FakeDelay {
+        fn delay_us(&mut self, us: u32) {
+            NOW.with(|now| now.set(now.get() + u64::from(us)));
+        }
+    }
+
+    /// Driver tests against a mocked I2C bus. These are instantiated once per embedded-hal
+    /// generation; the calling module provides the `i2c` mock module and the `init` and `done`
+    /// functions
+    macro_rules! driver_tests {
+        () => {
+            use crate::{Altitude, Error, TemperatureOffset, ADDRESS};
+
+            #[test]
+            fn firmware_version() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
+                    i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, 0xF3]),
+                ];
+                let mut scd30 = init(&expectations);
+                let version = scd30.get_firmware_version().unwrap();
+                assert_eq!([3, 66], version);
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn firmware_version_bad_crc() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
+                    // NOTE negated CRC byte in the response!
+                    i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, !0xF3]),
+                ];
+                let mut scd30 = init(&expectations);
+                let res = scd30.get_firmware_version();
+                assert_eq!(Err(Error::InvalidCrc), res);
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn start_continuous_measurement() {
+                let expectations = vec![i2c::Transaction::write(
+                    ADDRESS,
+                    vec![0x00, 0x10, 0x03, 0xFC, 0x53],
+                )];
+                let mut scd30 = init(&expectations);
+                scd30.start_continuous_measurement(1_020).unwrap();
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn start_continuous_measurement_range_limits() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x02, 0xBC, 0x9A]),
+                    i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x05, 0x78, 0xB7]),
+                ];
+                let mut scd30 = init(&expectations);
+                scd30.start_continuous_measurement(700).unwrap();
+                scd30.start_continuous_measurement(1_400).unwrap();
 
-        let mut scd30 = Scd30::init(mock);
-        let res = scd30.get_firmware_version();
-        assert_eq!(Err(Error::InvalidCrc), res);
+                done(scd30); // verify expectations
+            }
 
-        scd30.destroy().done(); // verify expectations
+            #[test]
+            fn start_continuous_measurement_without_pressure_compensation() {
+                let expectations = vec![i2c::Transaction::write(
+                    ADDRESS,
+                    vec![0x00, 0x10, 0x00, 0x00, 0x81],
+                )];
+                let mut scd30 = init(&expectations);
+                scd30.start_continuous_measurement(0).unwrap();
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn start_continuous_measurement_out_of_range() {
+                // NOTE no I2C traffic is expected
+                let mut scd30 = init(&[]);
+                assert_eq!(
+                    Err(Error::InvalidArgument),
+                    scd30.start_continuous_measurement(699)
+                );
+                assert_eq!(
+                    Err(Error::InvalidArgument),
+                    scd30.start_continuous_measurement(1_401)
+                );
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn set_ambient_pressure() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x03, 0xFC, 0x53]),
+                    i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x02, 0xBC, 0x9A]),
+                ];
+                let mut scd30 = init(&expectations);
+                scd30.start_continuous_measurement(1_020).unwrap();
+                scd30.set_ambient_pressure(700).unwrap();
+                assert_eq!(
+                    Err(Error::InvalidArgument),
+                    scd30.set_ambient_pressure(1_500)
+                );
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn data_ready() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+                    i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x00, 0x81]),
+                ];
+                let mut scd30 = init(&expectations);
+                assert!(scd30.data_ready().unwrap());
+                assert!(!scd30.data_ready().unwrap());
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn data_ready_bad_crc() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                    // NOTE negated CRC byte in the response!
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, !0xB0]),
+                ];
+                let mut scd30 = init(&expectations);
+                assert_eq!(Err(Error::InvalidCrc), scd30.data_ready());
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn data_ready_unexpected_status() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x02, 0xE3]),
+                    i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x02, 0xE3]),
+                ];
+                let mut scd30 = init(&expectations);
+                assert_eq!(Err(Error::UnexpectedStatus(2)), scd30.data_ready());
+
+                scd30.set_response_validation(false);
+                assert_eq!(Ok(false), scd30.data_ready());
+
+                done(scd30); // verify expectations
+            }
+
+            /// Example response from the Interface Specification document
+            const MEASUREMENT: [u8; 18] = [
+                0x43, 0xDB, 0xCB, 0x8C, 0x2E, 0x8F, // CO2 = 439 ppm
+                0x41, 0xD9, 0x70, 0xE7, 0xFF, 0xF5, // temperature = 27.2 C
+                0x42, 0x43, 0xBF, 0x3A, 0x1B, 0x74, // humidity = 48.8 %
+            ];
+
+            #[test]
+            fn read_measurement() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+                    i2c::Transaction::read(ADDRESS, MEASUREMENT.to_vec()),
+                ];
+                let mut scd30 = init(&expectations);
+                let data = scd30.read_measurement().unwrap();
+                assert_eq!(439, data.co2.0 as u32);
+                assert_eq!(27.2, (data.temperature.0 * 10.).trunc() / 10.);
+                assert_eq!(48.8, (data.humidity.0 * 10.).trunc() / 10.);
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn read_measurement_bad_crc() {
+                for crc_pos in (2..MEASUREMENT.len()).step_by(3) {
+                    let mut response = MEASUREMENT;
+                    // NOTE negated CRC byte in the response!
+                    response[crc_pos] = !response[crc_pos];
+
+                    let expectations = vec![
+                        i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+                        i2c::Transaction::read(ADDRESS, response.to_vec()),
+                    ];
+                    let mut scd30 = init(&expectations);
+                    assert!(
+                        matches!(scd30.read_measurement(), Err(Error::InvalidCrc)),
+                        "corrupted CRC at byte {} was not detected",
+                        crc_pos
+                    );
+
+                    done(scd30); // verify expectations
+                }
+            }
+
+            #[test]
+            fn read_measurement_non_finite() {
+                let mut response = MEASUREMENT;
+                // CO2 = NaN
+                response[..6].copy_from_slice(&[0x7F, 0xC0, 0x64, 0x00, 0x00, 0x81]);
+
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+                    i2c::Transaction::read(ADDRESS, response.to_vec()),
+                    i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+                    i2c::Transaction::read(ADDRESS, response.to_vec()),
+                ];
+                let mut scd30 = init(&expectations);
+                assert!(matches!(
+                    scd30.read_measurement(),
+                    Err(Error::NonFiniteMeasurement)
+                ));
+
+                scd30.set_response_validation(false);
+                assert!(scd30.read_measurement().unwrap().co2.0.is_nan());
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn read_measurement_out_of_range() {
+                let mut response = MEASUREMENT;
+                // CO2 = 50000 ppm
+                response[..6].copy_from_slice(&[0x47, 0x43, 0xC8, 0x50, 0x00, 0x66]);
+
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+                    i2c::Transaction::read(ADDRESS, response.to_vec()),
+                    i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+                    i2c::Transaction::read(ADDRESS, response.to_vec()),
+                ];
+                let mut scd30 = init(&expectations);
+                assert!(matches!(
+                    scd30.read_measurement(),
+                    Err(Error::MeasurementOutOfRange)
+                ));
+
+                scd30.set_response_validation(false);
+                assert_eq!(50_000., scd30.read_measurement().unwrap().co2.0);
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn stop_continuous_measurement() {
+                let expectations = vec![i2c::Transaction::write(ADDRESS, vec![0x01, 0x04])];
+                let mut scd30 = init(&expectations);
+                scd30.stop_continuous_measurement().unwrap();
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn soft_reset() {
+                let expectations = vec![i2c::Transaction::write(ADDRESS, vec![0xD3, 0x04])];
+                let mut scd30 = init(&expectations);
+                scd30.soft_reset().unwrap();
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn set_measurement_interval() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x46, 0x00, 0x00, 0x02, 0xE3]),
+                    i2c::Transaction::write(ADDRESS, vec![0x46, 0x00, 0x07, 0x08, 0x96]),
+                ];
+                let mut scd30 = init(&expectations);
+                scd30.set_measurement_interval(2).unwrap();
+                scd30.set_measurement_interval(1_800).unwrap();
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn set_measurement_interval_out_of_range() {
+                // NOTE no I2C traffic is expected
+                let mut scd30 = init(&[]);
+                assert_eq!(
+                    Err(Error::InvalidArgument),
+                    scd30.set_measurement_interval(1)
+                );
+                assert_eq!(
+                    Err(Error::InvalidArgument),
+                    scd30.set_measurement_interval(1_801)
+                );
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn get_measurement_interval() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x46, 0x00]),
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x05, 0x74]),
+                ];
+                let mut scd30 = init(&expectations);
+                assert_eq!(5, scd30.get_measurement_interval().unwrap());
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn get_measurement_interval_bad_crc() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x46, 0x00]),
+                    // NOTE negated CRC byte in the response!
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x05, !0x74]),
+                ];
+                let mut scd30 = init(&expectations);
+                assert_eq!(Err(Error::InvalidCrc), scd30.get_measurement_interval());
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn set_automatic_self_calibration() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x53, 0x06, 0x00, 0x01, 0xB0]),
+                    i2c::Transaction::write(ADDRESS, vec![0x53, 0x06, 0x00, 0x00, 0x81]),
+                ];
+                let mut scd30 = init(&expectations);
+                scd30.set_automatic_self_calibration(true).unwrap();
+                scd30.set_automatic_self_calibration(false).unwrap();
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn get_automatic_self_calibration() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x53, 0x06]),
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+                    i2c::Transaction::write(ADDRESS, vec![0x53, 0x06]),
+                    i2c::Transaction::read(ADDRESS, vec![0x00, 0x00, 0x81]),
+                ];
+                let mut scd30 = init(&expectations);
+                assert!(scd30.get_automatic_self_calibration().unwrap());
+                assert!(!scd30.get_automatic_self_calibration().unwrap());
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn set_forced_recalibration_value() {
+                let expectations = vec![i2c::Transaction::write(
+                    ADDRESS,
+                    vec![0x52, 0x04, 0x01, 0xC2, 0x50],
+                )];
+                let mut scd30 = init(&expectations);
+                scd30.set_forced_recalibration_value(450).unwrap();
+                // NOTE out of range values never reach the bus
+                assert_eq!(
+                    Err(Error::InvalidArgument),
+                    scd30.set_forced_recalibration_value(399)
+                );
+                assert_eq!(
+                    Err(Error::InvalidArgument),
+                    scd30.set_forced_recalibration_value(2_001)
+                );
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn get_forced_recalibration_value() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x52, 0x04]),
+                    i2c::Transaction::read(ADDRESS, vec![0x01, 0x90, 0x4C]),
+                ];
+                let mut scd30 = init(&expectations);
+                assert_eq!(400, scd30.get_forced_recalibration_value().unwrap());
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn set_temperature_offset() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x54, 0x03, 0x01, 0xF4, 0x33]),
+                    i2c::Transaction::write(ADDRESS, vec![0x54, 0x03, 0x00, 0xFA, 0xD8]),
+                ];
+                let mut scd30 = init(&expectations);
+                scd30
+                    .set_temperature_offset(TemperatureOffset::from_centikelvin(500))
+                    .unwrap();
+                scd30
+                    .set_temperature_offset(TemperatureOffset::from_kelvin(2.5).unwrap())
+                    .unwrap();
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn get_temperature_offset() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x54, 0x03]),
+                    i2c::Transaction::read(ADDRESS, vec![0x01, 0x54, 0xCF]),
+                ];
+                let mut scd30 = init(&expectations);
+                let offset = scd30.get_temperature_offset().unwrap();
+                assert_eq!(340, offset.as_centikelvin());
+                assert_eq!(3.4, offset.as_kelvin());
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn set_altitude_compensation() {
+                let expectations = vec![i2c::Transaction::write(
+                    ADDRESS,
+                    vec![0x51, 0x02, 0x03, 0xE8, 0xD4],
+                )];
+                let mut scd30 = init(&expectations);
+                scd30
+                    .set_altitude_compensation(Altitude::from_meters(1_000))
+                    .unwrap();
+
+                done(scd30); // verify expectations
+            }
+
+            #[test]
+            fn get_altitude_compensation() {
+                let expectations = vec![
+                    i2c::Transaction::write(ADDRESS, vec![0x51, 0x02]),
+                    i2c::Transaction::read(ADDRESS, vec![0x03, 0xE8, 0xD4]),
+                    i2c::Transaction::write(ADDRESS, vec![0x51, 0x02]),
+                    // NOTE negated CRC byte in the response!
+                    i2c::Transaction::read(ADDRESS, vec![0x03, 0xE8, !0xD4]),
+                ];
+                let mut scd30 = init(&expectations);
+                assert_eq!(
+                    Altitude::from_meters(1_000),
+                    scd30.get_altitude_compensation().unwrap()
+                );
+                assert_eq!(Err(Error::InvalidCrc), scd30.get_altitude_compensation());
+
+                done(scd30); // verify expectations
+            }
+        };
+    }
+
+    /// Tests against the embedded-hal 0.2 traits
+    mod eh0 {
+        use core::{cell::RefCell, time::Duration};
+
+        use embedded_hal::blocking::{self, delay::DelayUs};
+        use embedded_hal_mock::eh0::{delay::NoopDelay, i2c, MockError};
+
+        use super::{FakeDelay, FakeInstant};
+        use crate::{ByRef, I2cTransport, Scd30};
+
+        type Driver = Scd30<I2cTransport<i2c::Mock, NoopDelay>>;
+
+        fn init(expectations: &[i2c::Transaction]) -> Driver {
+            Scd30::init(i2c::Mock::new(expectations), NoopDelay::new())
+        }
+
+        fn done(scd30: Driver) {
+            scd30.destroy().0.done();
+        }
+
+        driver_tests!();
+
+        /// A delay provider that records the requested delays
+        #[derive(Default)]
+        struct RecordingDelay(Vec<u32>);
+
+        impl DelayUs<u32> for RecordingDelay {
+            fn delay_us(&mut self, us: u32) {
+                self.0.push(us);
+            }
+        }
+
+        #[test]
+        fn delay_between_command_and_response() {
+            let expectations = vec![
+                i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x00, 0x00, 0x81]),
+                i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+            ];
+            let mock = i2c::Mock::new(&expectations);
+
+            let mut scd30 = Scd30::init(mock, RecordingDelay::default());
+            scd30.start_continuous_measurement(0).unwrap();
+            scd30.data_ready().unwrap();
+
+            let (mut mock, delay) = scd30.destroy();
+            mock.done(); // verify expectations
+
+            // NOTE commands without a response don't need to wait
+            assert_eq!(vec![3_000], delay.0);
+        }
+
+        #[test]
+        fn wait_for_measurement() {
+            let expectations = vec![
+                i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                i2c::Transaction::read(ADDRESS, vec![0x00, 0x00, 0x81]),
+                i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+                i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+                i2c::Transaction::read(ADDRESS, MEASUREMENT.to_vec()),
+            ];
+            let mut scd30 = init(&expectations);
+            let data = scd30
+                .wait_for_measurement::<FakeInstant, _>(
+                    &mut FakeDelay,
+                    Duration::from_millis(100),
+                    Duration::from_secs(1),
+                )
+                .unwrap();
+            assert_eq!(439, data.co2.0 as u32);
+
+            done(scd30); // verify expectations
+        }
+
+        #[test]
+        fn wait_for_measurement_timeout() {
+            // polls at 0, 500 and 1000 ms
+            let expectations: Vec<_> = (0..3)
+                .flat_map(|_| {
+                    vec![
+                        i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                        i2c::Transaction::read(ADDRESS, vec![0x00, 0x00, 0x81]),
+                    ]
+                })
+                .collect();
+            let mut scd30 = init(&expectations);
+            assert!(matches!(
+                scd30.wait_for_measurement::<FakeInstant, _>(
+                    &mut FakeDelay,
+                    Duration::from_millis(500),
+                    Duration::from_secs(1),
+                ),
+                Err(Error::Timeout)
+            ));
+
+            done(scd30); // verify expectations
+        }
+
+        #[test]
+        fn borrowed_bus() {
+            let expectations = vec![
+                i2c::Transaction::write(ADDRESS, vec![0x01, 0x04]),
+                // another device on the same bus
+                i2c::Transaction::write(0x44, vec![0x24, 0x00]),
+            ];
+            let mut mock = i2c::Mock::new(&expectations);
+            let mut delay = NoopDelay::new();
+
+            let mut scd30 = Scd30::init(ByRef(&mut mock), ByRef(&mut delay));
+            scd30.stop_continuous_measurement().unwrap();
+
+            blocking::i2c::Write::write(&mut mock, 0x44, &[0x24, 0x00]).unwrap();
+            mock.done(); // verify expectations
+        }
+
+        /// A minimal shared-bus proxy; every transfer borrows the bus
+        struct Proxy<'a>(&'a RefCell<i2c::Mock>);
+
+        impl blocking::i2c::Write for Proxy<'_> {
+            type Error = MockError;
+
+            fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), MockError> {
+                self.0.borrow_mut().write(address, bytes)
+            }
+        }
+
+        impl blocking::i2c::Read for Proxy<'_> {
+            type Error = MockError;
+
+            fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), MockError> {
+                self.0.borrow_mut().read(address, buffer)
+            }
+        }
+
+        #[test]
+        fn shared_bus() {
+            let expectations = vec![
+                i2c::Transaction::write(ADDRESS, vec![0x01, 0x04]),
+                // another device on the same bus
+                i2c::Transaction::write(0x44, vec![0x24, 0x00]),
+                i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+            ];
+            let bus = RefCell::new(i2c::Mock::new(&expectations));
+
+            let mut scd30 = Scd30::init(Proxy(&bus), NoopDelay::new());
+            let mut other = Proxy(&bus);
+            scd30.stop_continuous_measurement().unwrap();
+            blocking::i2c::Write::write(&mut other, 0x44, &[0x24, 0x00]).unwrap();
+            assert!(scd30.data_ready().unwrap());
+
+            bus.into_inner().done(); // verify expectations
+        }
+    }
+
+    /// Tests against the embedded-hal 1.0 traits
+    #[cfg(feature = "eh1")]
+    mod eh1 {
+        use embedded_hal_1::delay::DelayNs;
+        use embedded_hal_mock::eh1::{delay::NoopDelay, i2c};
+
+        use crate::{Eh1, I2cTransport, Scd30};
+
+        type Driver = Scd30<I2cTransport<Eh1<i2c::Mock>, Eh1<NoopDelay>>>;
+
+        fn init(expectations: &[i2c::Transaction]) -> Driver {
+            Scd30::init(Eh1(i2c::Mock::new(expectations)), Eh1(NoopDelay::new()))
+        }
+
+        fn done(scd30: Driver) {
+            let (Eh1(mut mock), _) = scd30.destroy();
+            mock.done();
+        }
+
+        driver_tests!();
+
+        /// A delay provider that records the requested delays, in microseconds
+        #[derive(Default)]
+        struct RecordingDelay(Vec<u32>);
+
+        impl DelayNs for RecordingDelay {
+            fn delay_ns(&mut self, _: u32) {
+                unreachable!()
+            }
+
+            fn delay_us(&mut self, us: u32) {
+                self.0.push(us);
+            }
+        }
+
+        #[test]
+        fn delay_between_command_and_response() {
+            let expectations = vec![
+                i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x00, 0x00, 0x81]),
+                i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+                i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+            ];
+            let mock = i2c::Mock::new(&expectations);
+
+            let mut scd30 = Scd30::init(Eh1(mock), Eh1(RecordingDelay::default()));
+            scd30.start_continuous_measurement(0).unwrap();
+            scd30.data_ready().unwrap();
+
+            let (Eh1(mut mock), Eh1(delay)) = scd30.destroy();
+            mock.done(); // verify expectations
+
+            // NOTE commands without a response don't need to wait
+            assert_eq!(vec![3_000], delay.0);
+        }
+    }
+
+    #[test]
+    fn temperature_offset_from_kelvin() {
+        assert_eq!(
+            Some(TemperatureOffset::from_centikelvin(123)),
+            TemperatureOffset::from_kelvin(1.234)
+        );
+        assert_eq!(
+            Some(TemperatureOffset::from_centikelvin(u16::MAX)),
+            TemperatureOffset::from_kelvin(655.35)
+        );
+        assert_eq!(None, TemperatureOffset::from_kelvin(-0.1));
+        assert_eq!(None, TemperatureOffset::from_kelvin(655.36));
+        assert_eq!(None, TemperatureOffset::from_kelvin(f32::NAN));
+        assert_eq!(None, TemperatureOffset::from_kelvin(f32::INFINITY));
     }
 
     #[test]
@@ -169,4 +1171,32 @@ mod tests {
         // example from the Interface Specification document
         assert_eq!(super::compute_crc(&[0xBE, 0xEF]), 0x92);
     }
+
+    #[test]
+    fn display_sensor_data() {
+        let data = SensorData {
+            co2: Ppm(439.),
+            temperature: Celsius(27.25),
+            humidity: RelativeHumidity(48.5),
+        };
+        assert_eq!(
+            "CO2: 439 ppm, temperature: 27.25 °C, humidity: 48.5 %RH",
+            data.to_string()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() -> postcard::Result<()> {
+        let data = SensorData {
+            co2: Ppm(439.),
+            temperature: Celsius(27.2),
+            humidity: RelativeHumidity(48.8),
+        };
+        let bytes = postcard::to_allocvec(&data)?;
+        // the units serialize as plain `f32`s
+        assert_eq!(12, bytes.len());
+        assert_eq!(data, postcard::from_bytes::<SensorData>(&bytes)?);
+        Ok(())
+    }
 }
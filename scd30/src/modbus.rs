@@ -0,0 +1,439 @@
+//! The SCD30 Modbus RTU interface
+
+use core::{marker::PhantomData, time::Duration};
+
+use crc_any::CRCu16;
+use embedded_hal::{
+    blocking::{self, delay::DelayUs},
+    serial,
+};
+
+use crate::{Command, Error, Instant, Transport, ADDRESS, MAX_RESPONSE_WORDS};
+
+/// "Read Holding Registers" function code
+const READ_HOLDING_REGISTERS: u8 = 0x03;
+
+/// "Write Single Holding Register" function code
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+
+/// Bit set in the function code of an exception response
+const EXCEPTION: u8 = 0x80;
+
+/// Value written to the registers of commands that take no argument
+const TRIGGER: u16 = 0x0001;
+
+/// Size of a request frame, and of the response to a register write: address, function code,
+/// 2 16-bit fields and the CRC
+const FRAME_LEN: usize = 8;
+
+/// Size of the header of a register read response: address, function code and byte count
+const READ_HEADER_LEN: usize = 3;
+
+/// Size of the header shared by all response frames: address and function code
+const HEADER_LEN: usize = 2;
+
+/// Size of an exception response: address, function code, exception code and the CRC
+const EXCEPTION_LEN: usize = 5;
+
+/// Size of the CRC at the end of every frame
+const CRC_LEN: usize = 2;
+
+/// Pause, in microseconds, between polls of the serial port while waiting for a byte; about
+/// the time it takes to transmit one byte at 19200 baud
+const POLL_INTERVAL_US: u32 = 500;
+
+/// Maximum time spent waiting for the bytes of a response
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The Modbus RTU interface of a SCD30 on the serial port `S`.
+/// The port must be configured for 19200 baud, 8 data bits, no parity and 1 stop bit; its
+/// `read` may wait for a byte for a short while, but must not block until one is received.
+/// The delay provider `D` is used to pause between polls of the port while waiting for a
+/// response, and the clock `C` to time the wait
+pub struct ModbusTransport<S, D, C> {
+    serial: S,
+    delay: D,
+    _clock: PhantomData<C>,
+}
+
+impl<E, S, D, C> ModbusTransport<S, D, C>
+where
+    S: serial::Read<u8, Error = E> + blocking::serial::Write<u8, Error = E>,
+    D: DelayUs<u32>,
+    C: Instant,
+{
+    /// Creates the transport; this consumes the serial port `S` and the delay provider `D`
+    pub fn new(serial: S, delay: D) -> Self {
+        ModbusTransport {
+            serial,
+            delay,
+            _clock: PhantomData,
+        }
+    }
+
+    /// Releases the serial port `S` and the delay provider `D`
+    pub fn release(self) -> (S, D) {
+        (self.serial, self.delay)
+    }
+
+    /// Sends a request frame made of a function code and 2 16-bit fields
+    fn request(&mut self, function: u8, fields: [u16; 2]) -> Result<[u8; FRAME_LEN], Error<E>> {
+        // discard what's left of earlier responses so that it isn't taken as part of this one
+        self.drain()?;
+
+        let [a, b] = fields[0].to_be_bytes();
+        let [c, d] = fields[1].to_be_bytes();
+        let mut frame = [ADDRESS, function, a, b, c, d, 0, 0];
+        let crc = compute_crc(&frame[..FRAME_LEN - CRC_LEN]).to_le_bytes();
+        frame[FRAME_LEN - CRC_LEN..].copy_from_slice(&crc);
+
+        self.serial.bwrite_all(&frame).map_err(Error::Serial)?;
+        self.serial.bflush().map_err(Error::Serial)?;
+
+        Ok(frame)
+    }
+
+    /// Discards the bytes already received
+    fn drain(&mut self) -> Result<(), Error<E>> {
+        loop {
+            match self.serial.read() {
+                Ok(_) => {}
+                Err(nb::Error::WouldBlock) => return Ok(()),
+                Err(nb::Error::Other(e)) => return Err(Error::Serial(e)),
+            }
+        }
+    }
+
+    /// Receives a response frame into `frame`, which must be exactly as long as the expected
+    /// response, and checks its address, function code and CRC.
+    /// An exception response, with which the sensor rejects a request, is reported as
+    /// `Error::InvalidResponse`
+    fn response(&mut self, function: u8, frame: &mut [u8]) -> Result<(), Error<E>> {
+        let start = C::now();
+        let (header, rest) = frame.split_at_mut(HEADER_LEN);
+        self.receive(header, start)?;
+
+        // NOTE the rest of a frame from another device is discarded before the next request
+        if header[0] != ADDRESS {
+            warn!("SCD30: response from unexpected address {:#04x}", header[0]);
+            return Err(Error::InvalidResponse);
+        }
+
+        if header[1] == function | EXCEPTION {
+            let mut exception = [0; EXCEPTION_LEN];
+            exception[..HEADER_LEN].copy_from_slice(header);
+            self.receive(&mut exception[HEADER_LEN..], start)?;
+            check_crc(&exception)?;
+            warn!("SCD30: exception response {:02x?}", exception);
+            return Err(Error::InvalidResponse);
+        }
+
+        self.receive(rest, start)?;
+        check_crc(frame)?;
+        let payload = &frame[..frame.len() - CRC_LEN];
+
+        if payload[1] != function {
+            warn!("SCD30: unexpected response frame {:02x?}", frame);
+            return Err(Error::InvalidResponse);
+        }
+
+        Ok(())
+    }
+
+    /// Fills `bytes` with received bytes. `start` is when the wait for the current response
+    /// began; this gives up once `RESPONSE_TIMEOUT` has elapsed since then
+    fn receive(&mut self, bytes: &mut [u8], start: C) -> Result<(), Error<E>> {
+        for byte in bytes {
+            *byte = loop {
+                match self.serial.read() {
+                    Ok(byte) => break byte,
+                    Err(nb::Error::WouldBlock) => {
+                        // NOTE this also counts the time that `read` spent waiting
+                        if start.elapsed() >= RESPONSE_TIMEOUT {
+                            warn!("SCD30: no response after {:?}", RESPONSE_TIMEOUT);
+                            return Err(Error::Timeout);
+                        }
+                        self.delay.delay_us(POLL_INTERVAL_US);
+                    }
+                    Err(nb::Error::Other(e)) => return Err(Error::Serial(e)),
+                }
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl<E, S, D, C> Transport for ModbusTransport<S, D, C>
+where
+    S: serial::Read<u8, Error = E> + blocking::serial::Write<u8, Error = E>,
+    D: DelayUs<u32>,
+    C: Instant,
+{
+    type Error = E;
+
+    fn write(&mut self, command: Command, argument: Option<u16>) -> Result<(), Error<E>> {
+        let value = argument.unwrap_or(TRIGGER);
+        let request = self.request(WRITE_SINGLE_REGISTER, [command.modbus_register(), value])?;
+
+        // the sensor echoes the request
+        let mut response = [0; FRAME_LEN];
+        self.response(WRITE_SINGLE_REGISTER, &mut response)?;
+        if response != request {
+            return Err(Error::InvalidResponse);
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self, command: Command, words: &mut [u16]) -> Result<(), Error<E>> {
+        if words.len() > MAX_RESPONSE_WORDS {
+            return Err(Error::InvalidArgument);
+        }
+
+        let data_len = 2 * words.len();
+        self.request(
+            READ_HOLDING_REGISTERS,
+            [command.modbus_register(), words.len() as u16],
+        )?;
+
+        let mut response = [0; READ_HEADER_LEN + 2 * MAX_RESPONSE_WORDS + CRC_LEN];
+        let response = &mut response[..READ_HEADER_LEN + data_len + CRC_LEN];
+        self.response(READ_HOLDING_REGISTERS, response)?;
+        if usize::from(response[2]) != data_len {
+            return Err(Error::InvalidResponse);
+        }
+
+        let data = &response[READ_HEADER_LEN..READ_HEADER_LEN + data_len];
+        for (chunk, word) in data.chunks_exact(2).zip(words) {
+            *word = u16::from_be_bytes([chunk[0], chunk[1]]);
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the CRC at the end of `frame`
+fn check_crc<E>(frame: &[u8]) -> Result<(), Error<E>> {
+    let (payload, crc) = frame.split_at(frame.len() - CRC_LEN);
+    if compute_crc(payload).to_le_bytes() != crc {
+        warn!("SCD30: invalid CRC in response frame {:02x?}", frame);
+        return Err(Error::InvalidCrc);
+    }
+
+    Ok(())
+}
+
+/// Computes the Modbus CRC-16 of a frame; it's transmitted in little endian order
+fn compute_crc(bytes: &[u8]) -> u16 {
+    let mut crc = CRCu16::create_crc(0xa001, 16, 0xffff, 0x0000, true);
+    crc.digest(bytes);
+    crc.get_crc()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::iter;
+
+    use embedded_hal_mock::eh0::serial::{Mock, Transaction};
+
+    use crate::{
+        tests::{FakeDelay, FakeInstant},
+        Command, Error, Scd30, Transport,
+    };
+
+    use super::{ModbusTransport, POLL_INTERVAL_US, RESPONSE_TIMEOUT};
+
+    type Modbus = ModbusTransport<Mock<u8>, FakeDelay, FakeInstant>;
+
+    fn init(expectations: &[Transaction<u8>]) -> Scd30<Modbus> {
+        Scd30::with_transport(ModbusTransport::new(Mock::new(expectations), FakeDelay))
+    }
+
+    fn done(scd30: Scd30<Modbus>) {
+        scd30.into_transport().release().0.done();
+    }
+
+    /// No byte has been received
+    fn idle() -> Transaction<u8> {
+        Transaction::read_error(nb::Error::WouldBlock)
+    }
+
+    #[test]
+    fn start_continuous_measurement() {
+        // example from the Modbus Interface Description document
+        let frame = vec![0x61, 0x06, 0x00, 0x36, 0x00, 0x00, 0x60, 0x64];
+        let expectations = [
+            idle(), // nothing to drain
+            Transaction::write_many(frame.clone()),
+            Transaction::flush(),
+            Transaction::read_many(frame),
+        ];
+        let mut scd30 = init(&expectations);
+        scd30.start_continuous_measurement(0).unwrap();
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn stop_continuous_measurement() {
+        let frame = vec![0x61, 0x06, 0x00, 0x37, 0x00, 0x01, 0xF0, 0x64];
+        let expectations = [
+            idle(), // nothing to drain
+            Transaction::write_many(frame.clone()),
+            Transaction::flush(),
+            Transaction::read_many(frame),
+        ];
+        let mut scd30 = init(&expectations);
+        scd30.stop_continuous_measurement().unwrap();
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn write_bad_echo() {
+        let expectations = [
+            idle(), // nothing to drain
+            Transaction::write_many(vec![0x61, 0x06, 0x00, 0x36, 0x00, 0x00, 0x60, 0x64]),
+            Transaction::flush(),
+            // NOTE the echo has a different value, with a valid CRC
+            Transaction::read_many(vec![0x61, 0x06, 0x00, 0x36, 0x00, 0x01, 0xA1, 0xA4]),
+        ];
+        let mut scd30 = init(&expectations);
+        assert_eq!(
+            Err(Error::InvalidResponse),
+            scd30.start_continuous_measurement(0)
+        );
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn read_measurement() {
+        let expectations = [
+            idle(), // nothing to drain
+            // example from the Modbus Interface Description document
+            Transaction::write_many(vec![0x61, 0x03, 0x00, 0x28, 0x00, 0x06, 0x4C, 0x60]),
+            Transaction::flush(),
+            Transaction::read_many(vec![
+                0x61, 0x03, 0x0C, // header
+                0x43, 0xDB, 0x8C, 0x2E, // CO2 = 439 ppm
+                0x41, 0xD9, 0xE7, 0xFF, // temperature = 27.2 C
+                0x42, 0x43, 0x3A, 0x1B, // humidity = 48.8 %
+                0x50, 0x07, // CRC
+            ]),
+        ];
+        let mut scd30 = init(&expectations);
+        let data = scd30.read_measurement().unwrap();
+        assert_eq!(439, data.co2.0 as u32);
+        assert_eq!(27, data.temperature.0 as u32);
+        assert_eq!(48, data.humidity.0 as u32);
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn read_bad_crc() {
+        let expectations = [
+            idle(), // nothing to drain
+            Transaction::write_many(vec![0x61, 0x03, 0x00, 0x20, 0x00, 0x01, 0x8C, 0x60]),
+            Transaction::flush(),
+            // NOTE negated CRC byte in the response!
+            Transaction::read_many(vec![0x61, 0x03, 0x02, 0x03, 0x42, 0xB8, !0x8D]),
+        ];
+        let mut scd30 = init(&expectations);
+        assert_eq!(Err(Error::InvalidCrc), scd30.get_firmware_version());
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn exception_response() {
+        let expectations = [
+            idle(), // nothing to drain
+            Transaction::write_many(vec![0x61, 0x06, 0x00, 0x25, 0x00, 0x02, 0x10, 0x60]),
+            Transaction::flush(),
+            // "illegal data value" exception
+            Transaction::read_many(vec![0x61, 0x86, 0x03, 0x02, 0x7F]),
+        ];
+        let mut scd30 = init(&expectations);
+        assert_eq!(
+            Err(Error::InvalidResponse),
+            scd30.set_measurement_interval(2)
+        );
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn short_response() {
+        let polls = (RESPONSE_TIMEOUT.as_micros() / u128::from(POLL_INTERVAL_US) + 1) as usize;
+        let mut expectations = vec![
+            idle(), // nothing to drain
+            Transaction::write_many(vec![0x61, 0x03, 0x00, 0x20, 0x00, 0x01, 0x8C, 0x60]),
+            Transaction::flush(),
+            // the CRC never arrives
+            Transaction::read_many(vec![0x61, 0x03, 0x02, 0x03, 0x42]),
+        ];
+        expectations.extend(iter::repeat_with(idle).take(polls));
+        let mut scd30 = init(&expectations);
+        assert_eq!(Err(Error::Timeout), scd30.get_firmware_version());
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn response_from_another_address() {
+        let expectations = [
+            idle(), // nothing to drain
+            Transaction::write_many(vec![0x61, 0x06, 0x00, 0x25, 0x00, 0x02, 0x10, 0x60]),
+            Transaction::flush(),
+            // NOTE wrong address; the rest of the frame is left for the next request to drain
+            Transaction::read_many(vec![0x62, 0x86]),
+        ];
+        let mut scd30 = init(&expectations);
+        assert_eq!(
+            Err(Error::InvalidResponse),
+            scd30.set_measurement_interval(2)
+        );
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn read_too_many_words() {
+        let mut transport: Modbus = ModbusTransport::new(Mock::new(&[]), FakeDelay);
+        // NOTE no serial traffic is expected
+        assert_eq!(
+            Err(Error::InvalidArgument),
+            transport.read(Command::ReadMeasurement, &mut [0; 7])
+        );
+
+        transport.release().0.done(); // verify expectations
+    }
+
+    #[test]
+    fn stale_bytes_are_drained() {
+        let expectations = [
+            // the tail of an earlier response
+            Transaction::read_many(vec![0x42, 0xB8, 0x8D]),
+            idle(),
+            Transaction::write_many(vec![0x61, 0x03, 0x00, 0x20, 0x00, 0x01, 0x8C, 0x60]),
+            Transaction::flush(),
+            Transaction::read_many(vec![0x61, 0x03, 0x02, 0x03, 0x42, 0xB8, 0x8D]),
+        ];
+        let mut scd30 = init(&expectations);
+        assert_eq!(Ok([3, 66]), scd30.get_firmware_version());
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn crc() {
+        // example from the Modbus Interface Description document
+        assert_eq!(
+            super::compute_crc(&[0x61, 0x06, 0x00, 0x36, 0x00, 0x00]),
+            0x6460
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Metrics derived from the sensor readings
+//!
+//! Everything here is `no_std` and doesn't depend on `libm`: the logarithm and exponential
+//! functions are implemented in this module
+
+use crate::{Celsius, Ppm, RelativeHumidity, SensorData};
+
+/// Magnus formula coefficients over water for -45..=60 °C, from Sensirion's
+/// "Dew-point Calculation" application note
+const MAGNUS_B: f32 = 17.62;
+const MAGNUS_C: f32 = 243.12;
+
+/// Saturation vapour pressure, in hPa, at 0 °C
+const MAGNUS_P0: f32 = 6.112;
+
+/// Water vapour density, in g/m³, per hPa of vapour pressure and kelvin: M(H2O) / R * 100
+const VAPOUR_DENSITY_FACTOR: f32 = 216.7;
+
+/// Standard atmospheric pressure, in millibar
+const STANDARD_PRESSURE: f32 = 1_013.25;
+
+/// Returns the dew point of air at the given `temperature` and `humidity`.
+/// The result is NaN if `humidity` is not positive
+pub fn dew_point(temperature: Celsius, humidity: RelativeHumidity) -> Celsius {
+    let t = temperature.0;
+    let gamma = ln(humidity.0 / 100.) + MAGNUS_B * t / (MAGNUS_C + t);
+    Celsius(MAGNUS_C * gamma / (MAGNUS_B - gamma))
+}
+
+/// Returns the absolute humidity, in g/m³, of air at the given `temperature` and `humidity`
+pub fn absolute_humidity(temperature: Celsius, humidity: RelativeHumidity) -> f32 {
+    let t = temperature.0;
+    let vapour_pressure = humidity.0 / 100. * MAGNUS_P0 * exp(MAGNUS_B * t / (MAGNUS_C + t));
+    VAPOUR_DENSITY_FACTOR * vapour_pressure / temperature.to_kelvin()
+}
+
+/// Normalises a CO2 reading taken without ambient pressure compensation, at `pressure` (in
+/// millibar), to standard atmospheric pressure
+pub fn co2_at_standard_pressure(co2: Ppm, pressure: u16) -> Ppm {
+    Ppm(co2.0 * STANDARD_PRESSURE / f32::from(pressure))
+}
+
+impl SensorData {
+    /// Returns the dew point; see `dew_point`
+    pub fn dew_point(&self) -> Celsius {
+        dew_point(self.temperature, self.humidity)
+    }
+
+    /// Returns the absolute humidity, in g/m³; see `absolute_humidity`
+    pub fn absolute_humidity(&self) -> f32 {
+        absolute_humidity(self.temperature, self.humidity)
+    }
+
+    /// Returns the CO2 concentration normalised to standard pressure; see
+    /// `co2_at_standard_pressure`
+    pub fn co2_at_standard_pressure(&self, pressure: u16) -> Ppm {
+        co2_at_standard_pressure(self.co2, pressure)
+    }
+}
+
+/// Natural logarithm
+fn ln(x: f32) -> f32 {
+    if x.is_nan() || x < 0. {
+        return f32::NAN;
+    }
+    if x == 0. {
+        return f32::NEG_INFINITY;
+    }
+    if x.is_infinite() {
+        return x;
+    }
+
+    // x = m * 2^e with m in [1, 2); subnormals are scaled into the normal range first
+    let (x, bias) = if x < f32::MIN_POSITIVE {
+        (x * (1u32 << 23) as f32, 23)
+    } else {
+        (x, 0)
+    };
+    let bits = x.to_bits();
+    let mut e = ((bits >> 23) & 0xff) as i32 - 127 - bias;
+    let mut m = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+    // center m around 1 to speed up the convergence of the series
+    if m > core::f32::consts::SQRT_2 {
+        m /= 2.;
+        e += 1;
+    }
+
+    // ln(m) = 2 atanh(s) = 2 (s + s^3 / 3 + s^5 / 5 + ...), with |s| < 0.172
+    let s = (m - 1.) / (m + 1.);
+    let s2 = s * s;
+    let series = s * (2. + s2 * (2. / 3. + s2 * (2. / 5. + s2 * (2. / 7. + s2 * (2. / 9.)))));
+    series + e as f32 * core::f32::consts::LN_2
+}
+
+/// High bits of ln(2), with the 8 low bits of the mantissa cleared
+const LN_2_HI: f32 = 0.693_145_75;
+/// ln(2) - `LN_2_HI`
+const LN_2_LO: f32 = 1.428_606_8e-6;
+
+/// Exponential function
+fn exp(x: f32) -> f32 {
+    if x.is_nan() {
+        return x;
+    }
+    // outside this range the result is 0 or not representable
+    if x < -104. {
+        return 0.;
+    }
+    if x > 88.8 {
+        return f32::INFINITY;
+    }
+
+    // x = k ln(2) + r, with |r| <= ln(2) / 2
+    let t = x * core::f32::consts::LOG2_E;
+    let k = if t < 0. { t - 0.5 } else { t + 0.5 } as i32;
+    // ln(2) is split in two parts so that `k * LN_2_HI` is exact
+    let r = (x - k as f32 * LN_2_HI) - k as f32 * LN_2_LO;
+
+    // Taylor series of e^r
+    let mut term = 1.;
+    let mut sum = 1.;
+    for n in 1..=8 {
+        term *= r / n as f32;
+        sum += term;
+    }
+
+    // multiply by 2^k in two steps so that neither factor over- or underflows
+    let half = k / 2;
+    sum * pow2(half) * pow2(k - half)
+}
+
+/// Returns 2^k for k in the normal exponent range -126..=127
+fn pow2(k: i32) -> f32 {
+    f32::from_bits(((k + 127) as u32) << 23)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Celsius, Ppm, RelativeHumidity, SensorData};
+
+    use super::{exp, ln};
+
+    fn assert_close(expected: f32, actual: f32, tolerance: f32) {
+        assert!(
+            (expected - actual).abs() <= tolerance,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn ln_matches_std() {
+        for &x in &[
+            1e-40f32,
+            1e-6,
+            0.01,
+            0.5,
+            1.,
+            2.,
+            10.,
+            1_013.25,
+            1e30,
+            f32::MAX,
+        ] {
+            assert_close(x.ln(), ln(x), x.ln().abs() * 1e-6 + 1e-6);
+        }
+        assert_eq!(f32::NEG_INFINITY, ln(0.));
+        assert!(ln(-1.).is_nan());
+    }
+
+    #[test]
+    fn exp_matches_std() {
+        for &x in &[-100f32, -20., -1., -0.1, 0., 0.1, 1., 2.5, 20., 88.] {
+            assert_close(x.exp(), exp(x), x.exp() * 1e-6);
+        }
+        assert_eq!(0., exp(-200.));
+        assert_eq!(f32::INFINITY, exp(200.));
+    }
+
+    #[test]
+    fn dew_point() {
+        // reference values computed with the same Magnus coefficients in double precision
+        let data = |temperature, humidity| SensorData {
+            co2: Ppm(439.),
+            temperature: Celsius(temperature),
+            humidity: RelativeHumidity(humidity),
+        };
+        assert_close(13.85, data(25., 50.).dew_point().0, 0.01);
+        assert_close(25., data(25., 100.).dew_point().0, 0.001);
+        assert_close(-9.20, data(0., 50.).dew_point().0, 0.01);
+        assert!(data(25., 0.).dew_point().0.is_nan());
+    }
+
+    #[test]
+    fn absolute_humidity() {
+        let data = |temperature, humidity| SensorData {
+            co2: Ppm(439.),
+            temperature: Celsius(temperature),
+            humidity: RelativeHumidity(humidity),
+        };
+        // saturated air holds about 23 g/m³ of water vapour at 25 °C
+        assert_close(23.0, data(25., 100.).absolute_humidity(), 0.1);
+        assert_close(11.5, data(25., 50.).absolute_humidity(), 0.05);
+        assert_close(0., data(25., 0.).absolute_humidity(), 0.);
+    }
+
+    #[test]
+    fn co2_at_standard_pressure() {
+        let data = SensorData {
+            co2: Ppm(800.),
+            temperature: Celsius(20.),
+            humidity: RelativeHumidity(40.),
+        };
+        assert_close(800.2, data.co2_at_standard_pressure(1_013).0, 0.01);
+        assert_close(1_013.25, data.co2_at_standard_pressure(800).0, 0.01);
+    }
+}
@@ -0,0 +1,442 @@
+//! Recording and replay of I2C traffic
+//!
+//! `Recorder` wraps an I2C bus and reports every transfer as a `Transaction`. A transaction
+//! prints, with `Display` or `defmt` (with the `defmt` feature), as one line of a recording:
+//!
+//! ``` text
+//! I2C <timestamp in µs> <W or R> <address> [<bytes>] [ERR]
+//! ```
+//!
+//! `Replayer` acts as the bus described by a recording. It ignores anything before `I2C` on a
+//! line, and lines without it, so the log output of a firmware that uses `Recorder` can be
+//! saved and replayed as is
+
+use core::{fmt, str};
+
+use embedded_hal::blocking::i2c;
+
+/// Size of the largest transfer that `Replayer` can replay
+pub const MAX_TRANSFER_LEN: usize = 64;
+
+/// Marks the start of a transaction in a recording
+const MARKER: &str = "I2C ";
+
+/// Direction of an I2C transfer, seen from the bus controller
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Write,
+    Read,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Write => "W",
+            Direction::Read => "R",
+        }
+    }
+}
+
+/// A recorded I2C transfer
+#[derive(Clone, Copy, Debug)]
+pub struct Transaction<'a> {
+    /// Start of the transfer, in microseconds
+    pub timestamp: u32,
+    pub direction: Direction,
+    pub address: u8,
+    /// Bytes written or read; empty if a read failed
+    pub bytes: &'a [u8],
+    /// `true` if the bus reported an error
+    pub failed: bool,
+}
+
+impl fmt::Display for Transaction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{} {} {:#04x} [",
+            MARKER,
+            self.timestamp,
+            self.direction.as_str(),
+            self.address
+        )?;
+        for (i, byte) in self.bytes.iter().enumerate() {
+            if i != 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{:#04x}", byte)?;
+        }
+        f.write_str("]")?;
+        if self.failed {
+            f.write_str(" ERR")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Transaction<'_> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "I2C {=u32} {=str} {=u8:x} {=[u8]:x}{=str}",
+            self.timestamp,
+            self.direction.as_str(),
+            self.address,
+            self.bytes,
+            if self.failed { " ERR" } else { "" }
+        )
+    }
+}
+
+/// Wraps the I2C bus `I` and reports every transfer to `sink`.
+/// `clock` returns the current time in microseconds; it may wrap around
+pub struct Recorder<I, C, S> {
+    i2c: I,
+    clock: C,
+    sink: S,
+}
+
+impl<I, C, S> Recorder<I, C, S>
+where
+    C: FnMut() -> u32,
+    S: FnMut(&Transaction),
+{
+    /// Creates the recorder; this consumes the I2C bus `I`
+    pub fn new(i2c: I, clock: C, sink: S) -> Self {
+        Recorder { i2c, clock, sink }
+    }
+
+    /// Releases the I2C bus `I`
+    pub fn release(self) -> I {
+        self.i2c
+    }
+}
+
+impl<E, I, C, S> i2c::Write for Recorder<I, C, S>
+where
+    I: i2c::Write<Error = E>,
+    C: FnMut() -> u32,
+    S: FnMut(&Transaction),
+{
+    type Error = E;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), E> {
+        let timestamp = (self.clock)();
+        let result = self.i2c.write(address, bytes);
+        (self.sink)(&Transaction {
+            timestamp,
+            direction: Direction::Write,
+            address,
+            bytes,
+            failed: result.is_err(),
+        });
+        result
+    }
+}
+
+impl<E, I, C, S> i2c::Read for Recorder<I, C, S>
+where
+    I: i2c::Read<Error = E>,
+    C: FnMut() -> u32,
+    S: FnMut(&Transaction),
+{
+    type Error = E;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), E> {
+        let timestamp = (self.clock)();
+        let result = self.i2c.read(address, buffer);
+        (self.sink)(&Transaction {
+            timestamp,
+            direction: Direction::Read,
+            address,
+            bytes: if result.is_ok() { buffer } else { &[] },
+            failed: result.is_err(),
+        });
+        result
+    }
+}
+
+/// Error raised by `Replayer`; `line` numbers start at 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayError {
+    /// The transfer doesn't match the one recorded at `line`
+    Mismatch { line: usize },
+    /// The transfer recorded at `line` failed
+    RecordedFailure { line: usize },
+    /// `line` can't be parsed
+    Malformed { line: usize },
+    /// The recording has no more transfers
+    EndOfRecording,
+}
+
+/// An I2C bus that replays a recording made with `Recorder`
+pub struct Replayer<'a> {
+    lines: str::Lines<'a>,
+    line: usize,
+}
+
+impl<'a> Replayer<'a> {
+    /// Creates a bus that replays `recording`
+    pub fn new(recording: &'a str) -> Self {
+        Replayer {
+            lines: recording.lines(),
+            line: 0,
+        }
+    }
+
+    /// Panics if the recording has transfers left
+    pub fn done(mut self) {
+        match self.next() {
+            Err(ReplayError::EndOfRecording) => {}
+            _ => panic!("transfer at line {} was not replayed", self.line),
+        }
+    }
+
+    /// Parses the next transfer and checks its direction and address
+    fn expect(&mut self, direction: Direction, address: u8) -> Result<Recorded, ReplayError> {
+        let recorded = self.next()?;
+        let line = self.line;
+        if recorded.direction != direction || recorded.address != address {
+            Err(ReplayError::Mismatch { line })
+        } else if recorded.failed {
+            Err(ReplayError::RecordedFailure { line })
+        } else {
+            Ok(recorded)
+        }
+    }
+
+    /// Parses the next transfer
+    fn next(&mut self) -> Result<Recorded, ReplayError> {
+        loop {
+            let text = self.lines.next().ok_or(ReplayError::EndOfRecording)?;
+            self.line += 1;
+
+            if let Some(start) = text.find(MARKER) {
+                return parse(&text[start + MARKER.len()..])
+                    .ok_or(ReplayError::Malformed { line: self.line });
+            }
+        }
+    }
+}
+
+impl i2c::Write for Replayer<'_> {
+    type Error = ReplayError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), ReplayError> {
+        let recorded = self.expect(Direction::Write, address)?;
+        if recorded.bytes() == bytes {
+            Ok(())
+        } else {
+            Err(ReplayError::Mismatch { line: self.line })
+        }
+    }
+}
+
+impl i2c::Read for Replayer<'_> {
+    type Error = ReplayError;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), ReplayError> {
+        let recorded = self.expect(Direction::Read, address)?;
+        if recorded.bytes().len() == buffer.len() {
+            buffer.copy_from_slice(recorded.bytes());
+            Ok(())
+        } else {
+            Err(ReplayError::Mismatch { line: self.line })
+        }
+    }
+}
+
+/// A transfer parsed from a recording
+struct Recorded {
+    direction: Direction,
+    address: u8,
+    bytes: [u8; MAX_TRANSFER_LEN],
+    len: usize,
+    failed: bool,
+}
+
+impl Recorded {
+    fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// Parses a transaction, without the leading marker
+fn parse(text: &str) -> Option<Recorded> {
+    let open = text.find('[')?;
+    let close = text.find(']')?;
+
+    let mut fields = text[..open].split_whitespace();
+    let _timestamp: u32 = fields.next()?.parse().ok()?;
+    let direction = match fields.next()? {
+        "W" => Direction::Write,
+        "R" => Direction::Read,
+        _ => return None,
+    };
+    let address = parse_hex(fields.next()?)?;
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let mut bytes = [0; MAX_TRANSFER_LEN];
+    let mut len = 0;
+    for byte in text[open + 1..close].split(',').map(str::trim) {
+        if byte.is_empty() {
+            continue;
+        }
+        *bytes.get_mut(len)? = parse_hex(byte)?;
+        len += 1;
+    }
+
+    let failed = match text[close + 1..].trim() {
+        "" => false,
+        "ERR" => true,
+        _ => return None,
+    };
+
+    Some(Recorded {
+        direction,
+        address,
+        bytes,
+        len,
+        failed,
+    })
+}
+
+/// Parses a byte written as `0x..`
+fn parse_hex(text: &str) -> Option<u8> {
+    let digits = text.strip_prefix("0x")?;
+    u8::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use embedded_hal::blocking::i2c::Write;
+    use embedded_hal_mock::eh0::{delay::NoopDelay, i2c};
+
+    use crate::{
+        tests::{FakeDelay, FakeInstant},
+        ByRef, Error, I2cTransport, Scd30, ADDRESS,
+    };
+
+    use super::{Recorder, ReplayError, Replayer};
+
+    /// Synthetic recording of `session`, made from the examples of the SCD30 Interface
+    /// Specification.
+    /// NOTE this checks the driver against the specification, not against real sensor traffic;
+    /// see the header of the file
+    const SYNTHETIC_SESSION: &str = include_str!("../recordings/synthetic_session.log");
+
+    /// Recording of `session` captured from a sensor with `cargo xtask record session`
+    const CAPTURED_SESSION: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/recordings/session.log");
+
+    /// The session that `cross/self-tests` records; keep the two in sync
+    fn session(
+        scd30: &mut Scd30<I2cTransport<Replayer, NoopDelay>>,
+    ) -> Result<(), Error<ReplayError>> {
+        scd30.stop_continuous_measurement()?;
+        assert_eq!([3, 66], scd30.get_firmware_version()?);
+        scd30.set_measurement_interval(2)?;
+        assert_eq!(2, scd30.get_measurement_interval()?);
+        scd30.start_continuous_measurement(1_020)?;
+        let data = scd30.wait_for_measurement::<FakeInstant, _>(
+            &mut FakeDelay,
+            Duration::from_millis(100),
+            Duration::from_secs(3),
+        )?;
+        assert!(data.co2.is_in_range());
+        assert!(data.temperature.is_in_range());
+        assert!(data.humidity.is_in_range());
+        scd30.stop_continuous_measurement()
+    }
+
+    fn replay(recording: &str) {
+        let mut scd30 = Scd30::init(Replayer::new(recording), NoopDelay::new());
+        session(&mut scd30).unwrap();
+
+        scd30.destroy().0.done(); // verify that the whole recording was replayed
+    }
+
+    #[test]
+    fn replay_synthetic_session() {
+        replay(SYNTHETIC_SESSION);
+    }
+
+    // NOTE `cargo xtask test host` runs this test once the capture is checked in
+    #[test]
+    #[ignore = "needs a capture made with `cargo xtask record session`"]
+    fn replay_captured_session() {
+        let recording = std::fs::read_to_string(CAPTURED_SESSION)
+            .unwrap_or_else(|e| panic!("couldn't read {}: {}", CAPTURED_SESSION, e));
+        replay(&recording);
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
+            i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, 0xF3]),
+            i2c::Transaction::write(0x44, vec![0x24, 0x00]).with_error(
+                embedded_hal_mock::eh0::MockError::Io(std::io::ErrorKind::Other),
+            ),
+        ];
+        let mock = i2c::Mock::new(&expectations);
+
+        let mut recording = String::new();
+        let mut now = 0;
+        let clock = || {
+            now += 3_000;
+            now
+        };
+        let sink =
+            |transaction: &super::Transaction| recording.push_str(&format!("{}\n", transaction));
+        let mut scd30 = Scd30::init(Recorder::new(mock, clock, sink), NoopDelay::new());
+        assert_eq!([3, 66], scd30.get_firmware_version().unwrap());
+        let (mut recorder, _) = scd30.destroy();
+        assert!(recorder.write(0x44, &[0x24, 0x00]).is_err());
+        recorder.release().done(); // verify expectations
+
+        assert_eq!(
+            "I2C 3000 W 0x61 [0xd1, 0x00]\n\
+             I2C 6000 R 0x61 [0x03, 0x42, 0xf3]\n\
+             I2C 9000 W 0x44 [0x24, 0x00] ERR\n",
+            recording
+        );
+
+        let mut replayer = Replayer::new(&recording);
+        let mut scd30 = Scd30::init(ByRef(&mut replayer), NoopDelay::new());
+        assert_eq!([3, 66], scd30.get_firmware_version().unwrap());
+        assert_eq!(
+            Err(ReplayError::RecordedFailure { line: 3 }),
+            replayer.write(0x44, &[0x24, 0x00])
+        );
+        replayer.done();
+    }
+
+    #[test]
+    fn replay_mismatch() {
+        let recording = "0.000001 INFO I2C 0 W 0x61 [0x01, 0x04]\n";
+
+        let mut scd30 = Scd30::init(Replayer::new(recording), NoopDelay::new());
+        assert_eq!(
+            Err(Error::I2c(ReplayError::Mismatch { line: 1 })),
+            scd30.soft_reset()
+        );
+        assert_eq!(
+            Err(Error::I2c(ReplayError::EndOfRecording)),
+            scd30.soft_reset()
+        );
+    }
+
+    #[test]
+    fn replay_malformed() {
+        let mut replayer = Replayer::new("# header\nI2C 0 X 0x61 []\n");
+        assert_eq!(
+            Err(ReplayError::Malformed { line: 2 }),
+            replayer.write(0x61, &[])
+        );
+    }
+}
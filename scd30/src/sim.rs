@@ -0,0 +1,436 @@
+//! A simulated SCD30 for host-side tests
+//!
+//! `Simulator` implements the same I2C traits as a bus with a SCD30 on it, and the delay trait
+//! by advancing the simulated time. Clones share the same sensor: hand one to the driver and
+//! keep another one to script measurements and advance time
+
+use core::time::Duration;
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use embedded_hal::blocking::{delay::DelayUs, i2c};
+
+use crate::{
+    compute_crc, Celsius, Command, Ppm, RelativeHumidity, SensorData, ADDRESS,
+    AMBIENT_PRESSURE_RANGE, FORCED_RECALIBRATION_RANGE, MEASUREMENT_INTERVAL_RANGE, WORD_LEN,
+};
+
+/// Error raised by the simulated bus
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimError {
+    /// The sensor did not acknowledge the transfer: wrong address, unknown command, invalid
+    /// argument or argument CRC, or a read with no response pending
+    Nack,
+}
+
+/// A simulated SCD30 and the I2C bus it sits on
+#[derive(Clone, Default)]
+pub struct Simulator {
+    sensor: Rc<RefCell<Sensor>>,
+}
+
+impl Simulator {
+    /// Creates a sensor in its power-on state: idle, with a 2 second measurement interval
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the values reported by the next measurement; once the queue is drained,
+    /// every new measurement repeats the last values
+    pub fn push_measurement(&self, data: SensorData) {
+        self.sensor.borrow_mut().script.push_back(data);
+    }
+
+    /// Advances the simulated time, producing a measurement whenever an interval elapses
+    pub fn advance(&self, duration: Duration) {
+        self.sensor
+            .borrow_mut()
+            .advance(duration.as_micros() as u64);
+    }
+
+    /// Sets the firmware version reported by the sensor
+    pub fn set_firmware_version(&self, version: [u8; 2]) {
+        self.sensor.borrow_mut().firmware_version = version;
+    }
+
+    /// Returns `true` while continuous measurement is running
+    pub fn is_measuring(&self) -> bool {
+        self.sensor.borrow().measuring
+    }
+
+    /// Returns the ambient pressure, in millibar, last passed to "start continuous
+    /// measurement"; 0 means pressure compensation is disabled
+    pub fn ambient_pressure(&self) -> u16 {
+        self.sensor.borrow().ambient_pressure
+    }
+}
+
+impl i2c::Write for Simulator {
+    type Error = SimError;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), SimError> {
+        if address != ADDRESS {
+            return Err(SimError::Nack);
+        }
+
+        let (code, argument) = match *bytes {
+            [a, b] => ([a, b], None),
+            [a, b, c, d, crc] => {
+                if compute_crc(&[c, d]) != crc {
+                    return Err(SimError::Nack);
+                }
+                ([a, b], Some(u16::from_be_bytes([c, d])))
+            }
+            _ => return Err(SimError::Nack),
+        };
+        let command = Command::from_i2c_code(code).ok_or(SimError::Nack)?;
+
+        self.sensor.borrow_mut().execute(command, argument)
+    }
+}
+
+impl i2c::Read for Simulator {
+    type Error = SimError;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), SimError> {
+        if address != ADDRESS {
+            return Err(SimError::Nack);
+        }
+
+        let words = self
+            .sensor
+            .borrow_mut()
+            .response
+            .take()
+            .ok_or(SimError::Nack)?;
+        if buffer.len() > words.len() * WORD_LEN {
+            return Err(SimError::Nack);
+        }
+
+        let bytes = words.iter().flat_map(|word| {
+            let [high, low] = word.to_be_bytes();
+            vec![high, low, compute_crc(&[high, low])]
+        });
+        for (slot, byte) in buffer.iter_mut().zip(bytes) {
+            *slot = byte;
+        }
+
+        Ok(())
+    }
+}
+
+impl DelayUs<u32> for Simulator {
+    fn delay_us(&mut self, us: u32) {
+        self.sensor.borrow_mut().advance(u64::from(us));
+    }
+}
+
+/// State of the simulated sensor
+struct Sensor {
+    measuring: bool,
+    ambient_pressure: u16,
+    /// Measurement interval, in seconds
+    interval: u16,
+    /// Time elapsed, in microseconds, since the last measurement
+    elapsed_us: u64,
+    data_ready: bool,
+    /// Values of the last measurement
+    sample: SensorData,
+    /// Values of the upcoming measurements
+    script: VecDeque<SensorData>,
+    automatic_self_calibration: bool,
+    forced_recalibration_value: u16,
+    temperature_offset: u16,
+    altitude: u16,
+    firmware_version: [u8; 2],
+    /// Words to return on the next read
+    response: Option<Vec<u16>>,
+}
+
+impl Default for Sensor {
+    fn default() -> Self {
+        Sensor {
+            measuring: false,
+            ambient_pressure: 0,
+            interval: 2,
+            elapsed_us: 0,
+            data_ready: false,
+            sample: SensorData {
+                co2: Ppm(0.),
+                temperature: Celsius(0.),
+                humidity: RelativeHumidity(0.),
+            },
+            script: VecDeque::new(),
+            automatic_self_calibration: false,
+            forced_recalibration_value: 400,
+            temperature_offset: 0,
+            altitude: 0,
+            firmware_version: [3, 66],
+            response: None,
+        }
+    }
+}
+
+impl Sensor {
+    fn advance(&mut self, us: u64) {
+        if !self.measuring {
+            return;
+        }
+
+        let interval_us = u64::from(self.interval) * 1_000_000;
+        self.elapsed_us += us;
+        while self.elapsed_us >= interval_us {
+            self.elapsed_us -= interval_us;
+            if let Some(data) = self.script.pop_front() {
+                self.sample = data;
+            }
+            self.data_ready = true;
+        }
+    }
+
+    fn execute(&mut self, command: Command, argument: Option<u16>) -> Result<(), SimError> {
+        // a new command discards the response to the previous one
+        self.response = None;
+
+        match (command, argument) {
+            (Command::StartContinuousMeasurement, Some(pressure)) => {
+                if pressure != 0 && !AMBIENT_PRESSURE_RANGE.contains(&pressure) {
+                    return Err(SimError::Nack);
+                }
+                // restarting only updates the pressure compensation
+                if !self.measuring {
+                    self.measuring = true;
+                    self.elapsed_us = 0;
+                }
+                self.ambient_pressure = pressure;
+            }
+            (Command::StopContinuousMeasurement, None) => self.measuring = false,
+            (Command::MeasurementInterval, Some(interval)) => {
+                if !MEASUREMENT_INTERVAL_RANGE.contains(&interval) {
+                    return Err(SimError::Nack);
+                }
+                self.interval = interval;
+            }
+            (Command::MeasurementInterval, None) => self.respond(&[self.interval]),
+            (Command::DataReady, None) => self.respond(&[u16::from(self.data_ready)]),
+            (Command::ReadMeasurement, None) => {
+                self.data_ready = false;
+                let mut words = vec![];
+                for value in [
+                    self.sample.co2.0,
+                    self.sample.temperature.0,
+                    self.sample.humidity.0,
+                ] {
+                    let bits = value.to_bits();
+                    words.push((bits >> 16) as u16);
+                    words.push(bits as u16);
+                }
+                self.respond(&words);
+            }
+            (Command::AutomaticSelfCalibration, Some(enabled)) => {
+                if enabled > 1 {
+                    return Err(SimError::Nack);
+                }
+                self.automatic_self_calibration = enabled == 1;
+            }
+            (Command::AutomaticSelfCalibration, None) => {
+                self.respond(&[u16::from(self.automatic_self_calibration)])
+            }
+            (Command::ForcedRecalibrationValue, Some(reference)) => {
+                if !FORCED_RECALIBRATION_RANGE.contains(&reference) {
+                    return Err(SimError::Nack);
+                }
+                self.forced_recalibration_value = reference;
+            }
+            (Command::ForcedRecalibrationValue, None) => {
+                self.respond(&[self.forced_recalibration_value])
+            }
+            (Command::TemperatureOffset, Some(offset)) => self.temperature_offset = offset,
+            (Command::TemperatureOffset, None) => self.respond(&[self.temperature_offset]),
+            (Command::AltitudeCompensation, Some(altitude)) => self.altitude = altitude,
+            (Command::AltitudeCompensation, None) => self.respond(&[self.altitude]),
+            (Command::FirmwareVersion, None) => {
+                self.respond(&[u16::from_be_bytes(self.firmware_version)])
+            }
+            // the configuration, including the measurement mode, is kept in non-volatile memory
+            (Command::SoftReset, None) => {
+                self.data_ready = false;
+                self.elapsed_us = 0;
+            }
+            _ => return Err(SimError::Nack),
+        }
+
+        Ok(())
+    }
+
+    fn respond(&mut self, words: &[u16]) {
+        self.response = Some(words.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use embedded_hal::blocking::i2c::{Read, Write};
+
+    use crate::{
+        Celsius, I2cTransport, Ppm, RelativeHumidity, Scd30, SensorData, TemperatureOffset, ADDRESS,
+    };
+
+    use super::{SimError, Simulator};
+
+    fn init(sim: &Simulator) -> Scd30<I2cTransport<Simulator, Simulator>> {
+        Scd30::init(sim.clone(), sim.clone())
+    }
+
+    fn data(co2: f32, temperature: f32, humidity: f32) -> SensorData {
+        SensorData {
+            co2: Ppm(co2),
+            temperature: Celsius(temperature),
+            humidity: RelativeHumidity(humidity),
+        }
+    }
+
+    #[test]
+    fn data_ready_after_interval() {
+        let sim = Simulator::new();
+        sim.push_measurement(data(439., 27.2, 48.8));
+        let mut scd30 = init(&sim);
+
+        scd30.start_continuous_measurement(1_020).unwrap();
+        assert!(sim.is_measuring());
+        assert_eq!(1_020, sim.ambient_pressure());
+
+        sim.advance(Duration::from_millis(1_900));
+        assert!(!scd30.data_ready().unwrap());
+
+        sim.advance(Duration::from_millis(100));
+        assert!(scd30.data_ready().unwrap());
+
+        let measurement = scd30.read_measurement().unwrap();
+        assert_eq!(Ppm(439.), measurement.co2);
+        assert_eq!(Celsius(27.2), measurement.temperature);
+        assert_eq!(RelativeHumidity(48.8), measurement.humidity);
+
+        // reading the measurement clears the flag
+        assert!(!scd30.data_ready().unwrap());
+    }
+
+    #[test]
+    fn configured_interval() {
+        let sim = Simulator::new();
+        let mut scd30 = init(&sim);
+
+        scd30.set_measurement_interval(5).unwrap();
+        assert_eq!(5, scd30.get_measurement_interval().unwrap());
+        scd30.start_continuous_measurement(0).unwrap();
+
+        sim.advance(Duration::from_secs(4));
+        assert!(!scd30.data_ready().unwrap());
+
+        sim.advance(Duration::from_secs(1));
+        assert!(scd30.data_ready().unwrap());
+    }
+
+    #[test]
+    fn scripted_measurements() {
+        let sim = Simulator::new();
+        sim.push_measurement(data(400., 20., 40.));
+        sim.push_measurement(data(800., 21., 45.));
+        let mut scd30 = init(&sim);
+        scd30.start_continuous_measurement(0).unwrap();
+
+        let mut co2 = vec![];
+        for _ in 0..3 {
+            sim.advance(Duration::from_secs(2));
+            assert!(scd30.data_ready().unwrap());
+            co2.push(scd30.read_measurement().unwrap().co2.0);
+        }
+
+        // the last scripted values repeat
+        assert_eq!(vec![400., 800., 800.], co2);
+    }
+
+    #[test]
+    fn no_new_data_when_stopped() {
+        let sim = Simulator::new();
+        let mut scd30 = init(&sim);
+
+        sim.advance(Duration::from_secs(10));
+        assert!(!scd30.data_ready().unwrap());
+
+        scd30.start_continuous_measurement(0).unwrap();
+        sim.advance(Duration::from_secs(2));
+        scd30.stop_continuous_measurement().unwrap();
+        assert!(!sim.is_measuring());
+
+        // clear data ready flag
+        scd30.read_measurement().unwrap();
+
+        sim.advance(Duration::from_secs(10));
+        assert!(!scd30.data_ready().unwrap());
+    }
+
+    #[test]
+    fn settings() {
+        let sim = Simulator::new();
+        sim.set_firmware_version([3, 70]);
+        let mut scd30 = init(&sim);
+
+        assert_eq!([3, 70], scd30.get_firmware_version().unwrap());
+
+        scd30.set_automatic_self_calibration(true).unwrap();
+        assert!(scd30.get_automatic_self_calibration().unwrap());
+
+        scd30.set_forced_recalibration_value(450).unwrap();
+        assert_eq!(450, scd30.get_forced_recalibration_value().unwrap());
+
+        let offset = TemperatureOffset::from_centikelvin(150);
+        scd30.set_temperature_offset(offset).unwrap();
+        assert_eq!(
+            150,
+            scd30.get_temperature_offset().unwrap().as_centikelvin()
+        );
+
+        // the configuration survives a soft reset
+        scd30.soft_reset().unwrap();
+        assert!(scd30.get_automatic_self_calibration().unwrap());
+        assert_eq!(450, scd30.get_forced_recalibration_value().unwrap());
+    }
+
+    #[test]
+    fn response_crc() {
+        let mut sim = Simulator::new();
+
+        sim.write(ADDRESS, &[0xD1, 0x00]).unwrap();
+        let mut buffer = [0; 3];
+        sim.read(ADDRESS, &mut buffer).unwrap();
+        assert_eq!([0x03, 0x42, 0xF3], buffer);
+
+        // the response can only be read once
+        assert_eq!(Err(SimError::Nack), sim.read(ADDRESS, &mut buffer));
+    }
+
+    #[test]
+    fn nack() {
+        let mut sim = Simulator::new();
+
+        // wrong address
+        assert_eq!(Err(SimError::Nack), sim.write(0x62, &[0xD1, 0x00]));
+        // unknown command
+        assert_eq!(Err(SimError::Nack), sim.write(ADDRESS, &[0xFF, 0xFF]));
+        // NOTE negated CRC byte in the argument!
+        assert_eq!(
+            Err(SimError::Nack),
+            sim.write(ADDRESS, &[0x46, 0x00, 0x00, 0x02, !0xE3])
+        );
+        // measurement interval out of range
+        assert_eq!(
+            Err(SimError::Nack),
+            sim.write(ADDRESS, &[0x46, 0x00, 0x00, 0x01, 0xB0])
+        );
+        // response longer than the 1-word firmware version
+        sim.write(ADDRESS, &[0xD1, 0x00]).unwrap();
+        assert_eq!(Err(SimError::Nack), sim.read(ADDRESS, &mut [0; 6]));
+    }
+}
@@ -0,0 +1,176 @@
+//! Physical interfaces to the SCD30
+
+use embedded_hal::blocking::{delay::DelayUs, i2c};
+
+use crate::{
+    decode_words, encode_command_with_argument, Error, ADDRESS, MAX_RESPONSE_LEN,
+    MAX_RESPONSE_WORDS, READ_DELAY_US, WORD_LEN,
+};
+
+/// A SCD30 command, independent of the interface it's sent over
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    StartContinuousMeasurement,
+    StopContinuousMeasurement,
+    MeasurementInterval,
+    DataReady,
+    ReadMeasurement,
+    AutomaticSelfCalibration,
+    ForcedRecalibrationValue,
+    TemperatureOffset,
+    AltitudeCompensation,
+    FirmwareVersion,
+    SoftReset,
+}
+
+impl Command {
+    /// Every command supported by the driver
+    pub const ALL: [Command; 11] = [
+        Command::StartContinuousMeasurement,
+        Command::StopContinuousMeasurement,
+        Command::MeasurementInterval,
+        Command::DataReady,
+        Command::ReadMeasurement,
+        Command::AutomaticSelfCalibration,
+        Command::ForcedRecalibrationValue,
+        Command::TemperatureOffset,
+        Command::AltitudeCompensation,
+        Command::FirmwareVersion,
+        Command::SoftReset,
+    ];
+
+    /// Returns the command that uses `code` on the I2C interface, if any
+    pub fn from_i2c_code(code: [u8; 2]) -> Option<Command> {
+        Command::ALL
+            .iter()
+            .copied()
+            .find(|command| command.i2c_code() == code)
+    }
+
+    /// Returns the command code used on the I2C interface
+    pub fn i2c_code(self) -> [u8; 2] {
+        match self {
+            Command::StartContinuousMeasurement => [0x00, 0x10],
+            Command::StopContinuousMeasurement => [0x01, 0x04],
+            Command::MeasurementInterval => [0x46, 0x00],
+            Command::DataReady => [0x02, 0x02],
+            Command::ReadMeasurement => [0x03, 0x00],
+            Command::AutomaticSelfCalibration => [0x53, 0x06],
+            Command::ForcedRecalibrationValue => [0x52, 0x04],
+            Command::TemperatureOffset => [0x54, 0x03],
+            Command::AltitudeCompensation => [0x51, 0x02],
+            Command::FirmwareVersion => [0xd1, 0x00],
+            Command::SoftReset => [0xd3, 0x04],
+        }
+    }
+
+    /// Returns the holding register that the Modbus interface maps this command to
+    pub fn modbus_register(self) -> u16 {
+        match self {
+            Command::StartContinuousMeasurement => 0x0036,
+            Command::StopContinuousMeasurement => 0x0037,
+            Command::MeasurementInterval => 0x0025,
+            Command::DataReady => 0x0027,
+            Command::ReadMeasurement => 0x0028,
+            Command::AutomaticSelfCalibration => 0x003a,
+            Command::ForcedRecalibrationValue => 0x0039,
+            Command::TemperatureOffset => 0x003b,
+            Command::AltitudeCompensation => 0x0038,
+            Command::FirmwareVersion => 0x0020,
+            Command::SoftReset => 0x0034,
+        }
+    }
+}
+
+/// A physical interface to the SCD30.
+/// The driver decides which commands to send; the transport decides how they go on the wire
+pub trait Transport {
+    /// Error raised by the underlying bus
+    type Error;
+
+    /// Sends `command`, with a 16-bit `argument` if the command takes one
+    fn write(&mut self, command: Command, argument: Option<u16>) -> Result<(), Error<Self::Error>>;
+
+    /// Sends `command` and reads its response, `words.len()` 16-bit words, into `words`
+    fn read(&mut self, command: Command, words: &mut [u16]) -> Result<(), Error<Self::Error>>;
+}
+
+/// The I2C interface of a SCD30 on the bus `I`.
+/// The delay provider `D` is used to pause between a command and its response
+pub struct I2cTransport<I, D> {
+    i2c: I,
+    delay: D,
+}
+
+impl<E, I, D> I2cTransport<I, D>
+where
+    I: i2c::Read<Error = E> + i2c::Write<Error = E>,
+    D: DelayUs<u32>,
+{
+    /// Creates the transport; this consumes the I2C bus `I` and the delay provider `D`
+    pub fn new(i2c: I, delay: D) -> Self {
+        I2cTransport { i2c, delay }
+    }
+
+    /// Releases the I2C bus `I` and the delay provider `D`
+    pub fn release(self) -> (I, D) {
+        (self.i2c, self.delay)
+    }
+}
+
+impl<E, I, D> Transport for I2cTransport<I, D>
+where
+    I: i2c::Read<Error = E> + i2c::Write<Error = E>,
+    D: DelayUs<u32>,
+{
+    type Error = E;
+
+    fn write(&mut self, command: Command, argument: Option<u16>) -> Result<(), Error<E>> {
+        let command = command.i2c_code();
+        match argument {
+            Some(argument) => self
+                .i2c
+                .write(ADDRESS, &encode_command_with_argument(command, argument)),
+            None => self.i2c.write(ADDRESS, &command),
+        }
+        .map_err(Error::I2c)
+    }
+
+    fn read(&mut self, command: Command, words: &mut [u16]) -> Result<(), Error<E>> {
+        if words.len() > MAX_RESPONSE_WORDS {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut rd_buffer = [0u8; MAX_RESPONSE_LEN];
+        let rd_buffer = &mut rd_buffer[..words.len() * WORD_LEN];
+
+        self.i2c
+            .write(ADDRESS, &command.i2c_code())
+            .map_err(Error::I2c)?;
+        self.delay.delay_us(READ_DELAY_US);
+        self.i2c.read(ADDRESS, rd_buffer).map_err(Error::I2c)?;
+
+        decode_words(rd_buffer, words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_hal_mock::eh0::{delay::NoopDelay, i2c};
+
+    use crate::Error;
+
+    use super::{Command, I2cTransport, Transport};
+
+    #[test]
+    fn read_too_many_words() {
+        let mut transport = I2cTransport::new(i2c::Mock::new(&[]), NoopDelay::new());
+        // NOTE no I2C traffic is expected
+        assert_eq!(
+            Err(Error::InvalidArgument),
+            transport.read(Command::ReadMeasurement, &mut [0; 7])
+        );
+
+        transport.release().0.done(); // verify expectations
+    }
+}
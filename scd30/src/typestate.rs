@@ -0,0 +1,281 @@
+//! Typestate API over `crate::Scd30`
+//!
+//! The measurement state of the sensor is tracked in the type of the driver: measurements can
+//! only be read from a `Scd30<T, Measuring>`, which is obtained by starting continuous
+//! measurement on a `Scd30<T, Idle>`
+
+use core::{marker::PhantomData, time::Duration};
+
+use embedded_hal::blocking::delay::DelayUs;
+
+use crate::{Altitude, Error, Instant, SensorData, TemperatureOffset, Transport};
+
+/// Continuous measurement is stopped
+pub struct Idle;
+
+/// Continuous measurement is running
+pub struct Measuring;
+
+/// Result of a state change from `F` to `S`; on failure the driver is handed back in its
+/// original state along with the error
+pub type Transition<T, F, S> = Result<Scd30<T, S>, (Error<<T as Transport>::Error>, Scd30<T, F>)>;
+
+/// A SCD30 sensor, connected through the transport `T`, in the state `S`
+pub struct Scd30<T, S>
+where
+    T: Transport,
+{
+    driver: crate::Scd30<T>,
+    _state: PhantomData<S>,
+}
+
+impl<T> Scd30<T, Idle>
+where
+    T: Transport,
+{
+    /// Stops continuous measurement, which the sensor may have kept running from a previous
+    /// session, and wraps the untyped `driver`; on failure `driver` is handed back along with
+    /// the error
+    pub fn new(mut driver: crate::Scd30<T>) -> Result<Self, (Error<T::Error>, crate::Scd30<T>)> {
+        match driver.stop_continuous_measurement() {
+            Ok(()) => Ok(Scd30::from_driver(driver)),
+            Err(e) => Err((e, driver)),
+        }
+    }
+
+    /// Starts continuous measurement; see `crate::Scd30::start_continuous_measurement`
+    pub fn start_continuous_measurement(
+        mut self,
+        ambient_pressure: u16,
+    ) -> Transition<T, Idle, Measuring> {
+        match self.driver.start_continuous_measurement(ambient_pressure) {
+            Ok(()) => Ok(Scd30::from_driver(self.driver)),
+            Err(e) => Err((e, self)),
+        }
+    }
+}
+
+impl<T> Scd30<T, Measuring>
+where
+    T: Transport,
+{
+    /// Stops continuous measurement
+    pub fn stop_continuous_measurement(mut self) -> Transition<T, Measuring, Idle> {
+        match self.driver.stop_continuous_measurement() {
+            Ok(()) => Ok(Scd30::from_driver(self.driver)),
+            Err(e) => Err((e, self)),
+        }
+    }
+
+    /// Updates the ambient pressure compensation; see `crate::Scd30::set_ambient_pressure`
+    pub fn set_ambient_pressure(&mut self, ambient_pressure: u16) -> Result<(), Error<T::Error>> {
+        self.driver.set_ambient_pressure(ambient_pressure)
+    }
+
+    /// Returns `true` if a new measurement is available
+    pub fn data_ready(&mut self) -> Result<bool, Error<T::Error>> {
+        self.driver.data_ready()
+    }
+
+    /// Reads the last measurement
+    pub fn read_measurement(&mut self) -> Result<SensorData, Error<T::Error>> {
+        self.driver.read_measurement()
+    }
+
+    /// Waits for a new measurement and reads it; see `crate::Scd30::wait_for_measurement`
+    pub fn wait_for_measurement<C, D>(
+        &mut self,
+        delay: &mut D,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<SensorData, Error<T::Error>>
+    where
+        C: Instant,
+        D: DelayUs<u32>,
+    {
+        self.driver
+            .wait_for_measurement::<C, D>(delay, poll_interval, timeout)
+    }
+}
+
+impl<T, S> Scd30<T, S>
+where
+    T: Transport,
+{
+    fn from_driver(driver: crate::Scd30<T>) -> Self {
+        Scd30 {
+            driver,
+            _state: PhantomData,
+        }
+    }
+
+    /// Returns the untyped driver. The caller becomes responsible for tracking the measurement
+    /// state
+    pub fn into_untyped(self) -> crate::Scd30<T> {
+        self.driver
+    }
+
+    /// Returns the firmware version reported by the SCD30 sensor
+    pub fn get_firmware_version(&mut self) -> Result<[u8; 2], Error<T::Error>> {
+        self.driver.get_firmware_version()
+    }
+
+    /// Restarts the sensor without clearing its persisted configuration, which includes the
+    /// measurement state.
+    /// NOTE the sensor needs up to 2 seconds to boot after this command
+    pub fn soft_reset(&mut self) -> Result<(), Error<T::Error>> {
+        self.driver.soft_reset()
+    }
+
+    /// Sets the interval, in seconds, between continuous measurements.
+    /// `interval` must be in the range 2..=1800
+    pub fn set_measurement_interval(&mut self, interval: u16) -> Result<(), Error<T::Error>> {
+        self.driver.set_measurement_interval(interval)
+    }
+
+    /// Returns the interval, in seconds, between continuous measurements
+    pub fn get_measurement_interval(&mut self) -> Result<u16, Error<T::Error>> {
+        self.driver.get_measurement_interval()
+    }
+
+    /// Enables or disables automatic self-calibration (ASC)
+    pub fn set_automatic_self_calibration(&mut self, enabled: bool) -> Result<(), Error<T::Error>> {
+        self.driver.set_automatic_self_calibration(enabled)
+    }
+
+    /// Returns whether automatic self-calibration (ASC) is enabled
+    pub fn get_automatic_self_calibration(&mut self) -> Result<bool, Error<T::Error>> {
+        self.driver.get_automatic_self_calibration()
+    }
+
+    /// Recalibrates the sensor against a reference CO2 concentration, in ppm.
+    /// `co2` must be in the range 400..=2000
+    pub fn set_forced_recalibration_value(&mut self, co2: u16) -> Result<(), Error<T::Error>> {
+        self.driver.set_forced_recalibration_value(co2)
+    }
+
+    /// Returns the reference CO2 concentration, in ppm, used by the last forced recalibration
+    pub fn get_forced_recalibration_value(&mut self) -> Result<u16, Error<T::Error>> {
+        self.driver.get_forced_recalibration_value()
+    }
+
+    /// Sets the offset that the sensor subtracts from its temperature reading
+    pub fn set_temperature_offset(
+        &mut self,
+        offset: TemperatureOffset,
+    ) -> Result<(), Error<T::Error>> {
+        self.driver.set_temperature_offset(offset)
+    }
+
+    /// Returns the temperature offset applied by the sensor
+    pub fn get_temperature_offset(&mut self) -> Result<TemperatureOffset, Error<T::Error>> {
+        self.driver.get_temperature_offset()
+    }
+
+    /// Sets the altitude used for compensation of the CO2 reading
+    pub fn set_altitude_compensation(&mut self, altitude: Altitude) -> Result<(), Error<T::Error>> {
+        self.driver.set_altitude_compensation(altitude)
+    }
+
+    /// Returns the altitude used for compensation of the CO2 reading
+    pub fn get_altitude_compensation(&mut self) -> Result<Altitude, Error<T::Error>> {
+        self.driver.get_altitude_compensation()
+    }
+
+    /// Enables or disables the validation of the sensor's responses; see
+    /// `crate::Scd30::set_response_validation`
+    pub fn set_response_validation(&mut self, enabled: bool) {
+        self.driver.set_response_validation(enabled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use embedded_hal_mock::eh0::{delay::NoopDelay, i2c, MockError};
+
+    use crate::{Error, I2cTransport, ADDRESS};
+
+    use super::{Idle, Scd30};
+
+    type Driver = crate::Scd30<I2cTransport<i2c::Mock, NoopDelay>>;
+
+    fn init(expectations: &[i2c::Transaction]) -> Scd30<I2cTransport<i2c::Mock, NoopDelay>, Idle> {
+        let driver = Driver::init(i2c::Mock::new(expectations), NoopDelay::new());
+        Scd30::new(driver).ok().unwrap()
+    }
+
+    fn done<S>(scd30: Scd30<I2cTransport<i2c::Mock, NoopDelay>, S>) {
+        scd30.into_untyped().destroy().0.done();
+    }
+
+    #[test]
+    fn measurement_cycle() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x01, 0x04]),
+            i2c::Transaction::write(ADDRESS, vec![0x00, 0x10, 0x03, 0xFC, 0x53]),
+            i2c::Transaction::write(ADDRESS, vec![0x02, 0x02]),
+            i2c::Transaction::read(ADDRESS, vec![0x00, 0x01, 0xB0]),
+            i2c::Transaction::write(ADDRESS, vec![0x03, 0x00]),
+            // example response from the Interface Specification document
+            i2c::Transaction::read(
+                ADDRESS,
+                vec![
+                    0x43, 0xDB, 0xCB, 0x8C, 0x2E, 0x8F, // CO2 = 439 ppm
+                    0x41, 0xD9, 0x70, 0xE7, 0xFF, 0xF5, // temperature = 27.2 C
+                    0x42, 0x43, 0xBF, 0x3A, 0x1B, 0x74, // humidity = 48.8 %
+                ],
+            ),
+            i2c::Transaction::write(ADDRESS, vec![0x01, 0x04]),
+        ];
+        let scd30 = init(&expectations);
+
+        let mut scd30 = scd30.start_continuous_measurement(1_020).ok().unwrap();
+        assert!(scd30.data_ready().unwrap());
+        assert_eq!(439, scd30.read_measurement().unwrap().co2.0 as u32);
+        let scd30 = scd30.stop_continuous_measurement().ok().unwrap();
+
+        done(scd30); // verify expectations
+    }
+
+    #[test]
+    fn failed_new_returns_driver() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x01, 0x04])
+                .with_error(MockError::Io(ErrorKind::Other)),
+            i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
+            i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, 0xF3]),
+        ];
+        let driver = Driver::init(i2c::Mock::new(&expectations), NoopDelay::new());
+
+        let (error, mut driver) = match Scd30::new(driver) {
+            Ok(_) => panic!("failed stop was ignored"),
+            Err(e) => e,
+        };
+        assert_eq!(Error::I2c(MockError::Io(ErrorKind::Other)), error);
+        assert_eq!([3, 66], driver.get_firmware_version().unwrap());
+
+        driver.destroy().0.done(); // verify expectations
+    }
+
+    #[test]
+    fn failed_start_returns_idle_driver() {
+        let expectations = vec![
+            i2c::Transaction::write(ADDRESS, vec![0x01, 0x04]),
+            i2c::Transaction::write(ADDRESS, vec![0xD1, 0x00]),
+            i2c::Transaction::read(ADDRESS, vec![0x03, 0x42, 0xF3]),
+        ];
+        let scd30 = init(&expectations);
+
+        // NOTE no I2C traffic is expected for the invalid argument
+        let (error, mut scd30) = match scd30.start_continuous_measurement(1) {
+            Ok(_) => panic!("invalid ambient pressure was accepted"),
+            Err(e) => e,
+        };
+        assert_eq!(Error::InvalidArgument, error);
+        assert_eq!([3, 66], scd30.get_firmware_version().unwrap());
+
+        done(scd30); // verify expectations
+    }
+}
@@ -0,0 +1,171 @@
+//! Physical units of the sensor readings
+
+use core::{fmt, ops::RangeInclusive};
+
+/// Molar mass of CO2, in g/mol
+const CO2_MOLAR_MASS: f32 = 44.01;
+
+/// Molar gas constant, in J/(mol K)
+const GAS_CONSTANT: f32 = 8.314_46;
+
+/// 0 degrees Celsius, in kelvin
+const ZERO_CELSIUS: f32 = 273.15;
+
+/// CO2 concentration, in parts per million
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Ppm(pub f32);
+
+impl Ppm {
+    /// Measurement range of the sensor
+    pub const RANGE: RangeInclusive<f32> = 0.0..=40_000.0;
+
+    /// Returns `true` if the value is within the measurement range of the sensor
+    pub fn is_in_range(self) -> bool {
+        Self::RANGE.contains(&self.0)
+    }
+
+    /// Converts the concentration to a mass density, in mg/m³, for air at the given
+    /// `temperature` and `pressure` (in millibar)
+    pub fn to_milligrams_per_cubic_meter(self, temperature: Celsius, pressure: u16) -> f32 {
+        // ideal gas law: n / V = p / (R T)
+        let moles_per_cubic_meter =
+            f32::from(pressure) * 100. / (GAS_CONSTANT * temperature.to_kelvin());
+        self.0 * moles_per_cubic_meter * CO2_MOLAR_MASS / 1_000.
+    }
+}
+
+impl fmt::Display for Ppm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ppm", self.0)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Ppm {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=f32} ppm", self.0)
+    }
+}
+
+/// Temperature, in degrees Celsius
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Celsius(pub f32);
+
+impl Celsius {
+    /// Measurement range of the sensor
+    pub const RANGE: RangeInclusive<f32> = -40.0..=70.0;
+
+    /// Returns `true` if the value is within the measurement range of the sensor
+    pub fn is_in_range(self) -> bool {
+        Self::RANGE.contains(&self.0)
+    }
+
+    /// Converts the temperature to degrees Fahrenheit
+    pub fn to_fahrenheit(self) -> Fahrenheit {
+        Fahrenheit(self.0 * 9. / 5. + 32.)
+    }
+
+    /// Converts the temperature to kelvin
+    pub fn to_kelvin(self) -> f32 {
+        self.0 + ZERO_CELSIUS
+    }
+}
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} °C", self.0)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Celsius {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=f32} °C", self.0)
+    }
+}
+
+/// Temperature, in degrees Fahrenheit
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Fahrenheit(pub f32);
+
+impl Fahrenheit {
+    /// Converts the temperature to degrees Celsius
+    pub fn to_celsius(self) -> Celsius {
+        Celsius((self.0 - 32.) * 5. / 9.)
+    }
+}
+
+impl fmt::Display for Fahrenheit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} °F", self.0)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Fahrenheit {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=f32} °F", self.0)
+    }
+}
+
+/// Relative humidity, in percent
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct RelativeHumidity(pub f32);
+
+impl RelativeHumidity {
+    /// Measurement range of the sensor
+    pub const RANGE: RangeInclusive<f32> = 0.0..=100.0;
+
+    /// Returns `true` if the value is within the measurement range of the sensor
+    pub fn is_in_range(self) -> bool {
+        Self::RANGE.contains(&self.0)
+    }
+}
+
+impl fmt::Display for RelativeHumidity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} %RH", self.0)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for RelativeHumidity {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=f32} %RH", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Celsius, Fahrenheit, Ppm, RelativeHumidity};
+
+    #[test]
+    fn fahrenheit() {
+        assert_eq!(Fahrenheit(32.), Celsius(0.).to_fahrenheit());
+        assert_eq!(Fahrenheit(212.), Celsius(100.).to_fahrenheit());
+        assert_eq!(Celsius(-40.), Fahrenheit(-40.).to_celsius());
+        assert_eq!(Celsius(37.), Celsius(37.).to_fahrenheit().to_celsius());
+    }
+
+    #[test]
+    fn milligrams_per_cubic_meter() {
+        // 1 ppm of CO2 is 1.80 mg/m³ at 25 °C and 1 atm
+        let density = Ppm(1_000.).to_milligrams_per_cubic_meter(Celsius(25.), 1_013);
+        assert_eq!(1_798, density as u32);
+    }
+
+    #[test]
+    fn range() {
+        assert!(Ppm(439.).is_in_range());
+        assert!(!Ppm(-1.).is_in_range());
+        assert!(!Ppm(f32::NAN).is_in_range());
+        assert!(Celsius(-40.).is_in_range());
+        assert!(!Celsius(70.1).is_in_range());
+        assert!(RelativeHumidity(100.).is_in_range());
+        assert!(!RelativeHumidity(100.1).is_in_range());
+    }
+}
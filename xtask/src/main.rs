@@ -1,7 +1,7 @@
 #![allow(dead_code)]
 #![deny(unused_must_use)]
 
-use std::{env, path::PathBuf};
+use std::{env, path::PathBuf, str};
 
 use xshell::cmd;
 
@@ -10,18 +10,43 @@ fn main() -> Result<(), anyhow::Error> {
     let args = args.iter().map(|s| &**s).collect::<Vec<_>>();
 
     match &args[..] {
+        ["build", "no-std"] => build_no_std(),
+        ["record", "session"] => record_session(),
         ["test", "all"] => test_all(),
         ["test", "host"] => test_host(),
         ["test", "host-target"] => test_host_target(),
         ["test", "target"] => test_target(),
         _ => {
+            println!("USAGE cargo xtask build no-std");
+            println!("USAGE cargo xtask record session");
             println!("USAGE cargo xtask test [all|host|host-target|target]");
             Ok(())
         }
     }
 }
 
+/// Builds the `no_std` driver with every combination of its optional features
+fn build_no_std() -> Result<(), anyhow::Error> {
+    // `sim` is left out because it requires `std`
+    const FEATURES: [&str; 5] = ["async", "defmt", "eh1", "log", "serde"];
+    const TARGET: &str = "thumbv7em-none-eabihf";
+
+    let _p = xshell::pushd(root_dir())?;
+    for mask in 0..1 << FEATURES.len() {
+        let features = FEATURES
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| mask & (1 << i) != 0)
+            .map(|(_, feature)| *feature)
+            .collect::<Vec<_>>()
+            .join(",");
+        cmd!("cargo build -p scd30 --target {TARGET} --features {features}").run()?;
+    }
+    Ok(())
+}
+
 fn test_all() -> Result<(), anyhow::Error> {
+    build_no_std()?;
     test_host()?;
     test_target()?;
     test_host_target()
@@ -30,6 +55,45 @@ fn test_all() -> Result<(), anyhow::Error> {
 fn test_host() -> Result<(), anyhow::Error> {
     let _p = xshell::pushd(root_dir())?;
     cmd!("cargo test --workspace --exclude host-target-tests").run()?;
+    // run the driver tests against the embedded-hal 1.0 traits and the async driver as well
+    cmd!("cargo test -p scd30 --all-features").run()?;
+    // the host side of the serial protocol can be tested against a fake target
+    cmd!("cargo test -p host-target-tests protocol::").run()?;
+    // replay the I2C traffic captured from a sensor, if any
+    if CAPTURED_SESSION.iter().collect::<PathBuf>().exists() {
+        cmd!("cargo test -p scd30 replay_captured_session -- --ignored").run()?;
+    }
+    Ok(())
+}
+
+/// Recording of the I2C traffic of the `record_session` self-test, relative to `root_dir`
+const CAPTURED_SESSION: [&str; 3] = ["scd30", "recordings", "session.log"];
+
+/// Runs the self-tests and saves the I2C traffic that `record_session` logs
+fn record_session() -> Result<(), anyhow::Error> {
+    let output = {
+        let _p = xshell::pushd(root_dir().join("cross"))?;
+        cmd!("cargo test -p self-tests").output()?
+    };
+
+    // `scd30::record::Replayer` only needs the transactions, but the rest of the log line is
+    // kept as is
+    let mut recording = String::from(
+        "# i2c traffic of the `record_session` self-test in `cross/self-tests/tests/scd30.rs`, \
+         captured from a sensor with `cargo xtask record session`\n",
+    );
+    for stream in [&output.stdout, &output.stderr] {
+        for line in str::from_utf8(stream)?.lines() {
+            if line.contains("I2C ") {
+                recording.push_str(line);
+                recording.push('\n');
+            }
+        }
+    }
+
+    let path = root_dir().join(CAPTURED_SESSION.iter().collect::<PathBuf>());
+    xshell::write_file(&path, recording)?;
+    println!("saved {}", path.display());
     Ok(())
 }
 